@@ -1,13 +1,14 @@
 use clap::{Parser, Subcommand};
-use icapture_core::{device, Capture, Config};
+use icapture_core::{codec, device, storage, stream::PreviewMode, Capture, Config, Preset};
 use std::error::Error;
+use std::io;
 use std::thread;
 use std::time::Duration;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    #[arg(short, long, default_value = ".\\config.json")]
+    #[arg(short, long, default_value = "config.json")]
     config_file: String,
     #[command(subcommand)]
     action: Action,
@@ -28,8 +29,46 @@ enum Action {
     /// List available devices
     ListDevices,
 
+    /// Monitor the camera feed for motion
+    Monitor {
+        /// Duration of the monitoring in seconds
+        #[arg(short, long)]
+        duration: u32,
+    },
+
+    /// Manage camera control presets stored next to the config file
+    Preset {
+        #[command(subcommand)]
+        action: PresetAction,
+    },
+
     /// Preview the camera feed
-    Preview,
+    Preview {
+        /// Serve frames without a window and save them as latest.jpg in the data directory
+        #[arg(long)]
+        headless: bool,
+    },
+
+    /// List codec and container combinations supported by the local OpenCV build
+    ProbeCodecs,
+}
+
+#[derive(Subcommand, Debug)]
+enum PresetAction {
+    /// Save current camera controls as a preset
+    Save {
+        /// Name of the preset
+        name: String,
+    },
+
+    /// Apply a preset to the camera
+    Apply {
+        /// Name of the preset
+        name: String,
+    },
+
+    /// List available presets
+    List,
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -38,26 +77,88 @@ fn main() -> Result<(), Box<dyn Error>> {
     let args = Args::parse();
 
     let config = Config::new(&args.config_file);
+    codec::init_writer_options(&config.codec_params);
+    for path in storage::sweep_partial_files(&config.data_dir) {
+        eprintln!(
+            "removed partial file of an interrupted capture: {}",
+            path.display()
+        );
+    }
 
     match args.action {
         Action::GrabFrame => {
             let mut capture = Capture::new(&config)?;
-            capture.grab_frame()?;
+            println!("{}", capture.grab_frame()?.file_path.display());
             capture.dispose()?;
         }
         Action::GrabVideo { duration } => {
             let mut capture = Capture::new(&config)?;
             capture.start_grab_video()?;
             thread::sleep(Duration::from_secs(duration as u64));
-            capture.stop_grab_video()?;
+            for artifact in capture.stop_grab_video()? {
+                println!("{}", artifact.file_path.display());
+            }
             capture.dispose()?;
         }
         Action::ListDevices => println!("{:?}", device::enumerate_capture_devices()?),
-        Action::Preview => {
+        Action::Monitor { duration } => {
             let mut capture = Capture::new(&config)?;
-            capture.preview()?;
+            capture.start_motion_monitor()?;
+            thread::sleep(Duration::from_secs(duration as u64));
+            capture.stop_motion_monitor()?;
+            for event in capture.motion_events() {
+                println!("{:?}", event);
+            }
             capture.dispose()?;
         }
+        Action::Preset { action } => {
+            let preset_dir = Preset::dir(&args.config_file);
+            match action {
+                PresetAction::Save { name } => {
+                    let mut capture = Capture::new(&config)?;
+                    let file_path = capture.snapshot_preset(&name)?.save(&preset_dir)?;
+                    println!("preset saved to {}", file_path.display());
+                    capture.dispose()?;
+                }
+                PresetAction::Apply { name } => {
+                    let preset = Preset::load(&preset_dir, &name)?;
+                    let mut capture = Capture::new(&config)?;
+                    println!("{:?}", capture.apply_preset(&preset)?);
+                    capture.dispose()?;
+                }
+                PresetAction::List => {
+                    for name in Preset::list(&preset_dir)? {
+                        println!("{}", name);
+                    }
+                }
+            }
+        }
+        Action::Preview { headless } => {
+            let mut capture = Capture::new(&config)?;
+            if headless || config.stream.preview_mode == PreviewMode::Headless {
+                capture.start_preview(PreviewMode::Headless)?;
+                println!("headless preview started, press Enter to stop");
+                io::stdin().read_line(&mut String::new())?;
+                capture.stop_grab_video()?;
+            } else {
+                capture.preview()?;
+            }
+            capture.dispose()?;
+        }
+        Action::ProbeCodecs => {
+            for support in codec::probe_support() {
+                println!(
+                    "{:?}\t{:?}\t{}",
+                    support.codec,
+                    support.container,
+                    if support.supported {
+                        "supported"
+                    } else {
+                        "not supported"
+                    }
+                );
+            }
+        }
     }
 
     Ok(())
@@ -1,7 +1,15 @@
 //! Provides operations for saving frames and video from a specified capturing device.
 
 use crate::config::Config;
-use log::{debug, error, warn};
+use crate::preset::Preset;
+use chrono::{DateTime, Local};
+use codec::{Codec, CodecParams};
+use color::ColorMode;
+use controls::{Control, Controls};
+use events::{CaptureEvent, CaptureEventKind, EventBus};
+use log::{debug, error, info, warn};
+use motion::{MotionDetector, MotionEvent, MotionEventKind};
+use naming::{FileNamer, Naming};
 use opencv::{
     core::{self, Size},
     highgui, imgcodecs,
@@ -9,19 +17,42 @@ use opencv::{
     videoio::*,
     Error, Result,
 };
+use overlay::OverlayContext;
+use quality::{QualityAnalyzer, QualityIssue, QualityMetrics};
+use serde::Serialize;
 use std::{
+    collections::VecDeque,
+    fs,
+    path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{Receiver, Sender},
         Arc, Mutex,
     },
-    thread,
+    thread::{self, JoinHandle},
     time::{Duration, Instant},
 };
+use stream::{LiveFrame, PreviewMode};
 use thiserror::Error;
+use watchdog::{ReconnectEvent, ReconnectEventKind, StreamWatchdog};
 
 pub mod codec;
+pub mod color;
+pub mod composite;
+pub mod controls;
 pub mod device;
+pub mod events;
 mod file;
+pub mod mask;
+pub mod motion;
+pub mod multi;
+pub mod naming;
+pub mod overlay;
+pub mod quality;
+pub mod storage;
+pub mod stream;
+pub mod transform;
+pub mod watchdog;
 
 /// Defines possible capturing errors.
 #[derive(Error, Debug)]
@@ -29,21 +60,105 @@ pub enum CaptureError {
     /// Cannot create file or directory
     #[error("cannot create file or directory '{0}'")]
     CreateFileDirectory(String),
+    /// Cannot read or delete a file
+    #[error("cannot access file '{0}'")]
+    FileAccess(String),
+    /// File does not exist in the data directory
+    #[error("file '{0}' not found")]
+    FileNotFound(String),
     /// Cannot open capturing device
     #[error("cannot open capture device {0}")]
     DeviceOpen(String),
     /// Cannot grab a frame
     #[error("cannot grab a frame")]
     GrabFrame,
+    /// Frame quality thresholds are violated
+    #[error("frame quality check failed: {0:?}")]
+    FrameQuality(Vec<QualityIssue>),
+    /// File name is not a plain name inside the data directory
+    #[error("invalid file name '{0}'")]
+    InvalidFileName(String),
+    /// Invalid capturing configuration
+    #[error("invalid configuration: {0}")]
+    InvalidConfig(String),
     /// OpenCV error
     #[error("opencv error: {0}")]
     OpenCv(#[from] Error),
     /// Video capturing resource is busy
     #[error("resource is busy")]
     ResourceBusy,
+    /// Cannot open video writer with any of the configured codecs
+    #[error("cannot open video writer for '{0}'")]
+    VideoWriterOpen(String),
 }
 
-static IS_GRABBING: AtomicBool = AtomicBool::new(false);
+/// Defines a video file being recorded.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct VideoOutput {
+    /// Codec actually used for recording
+    pub codec: Codec,
+    /// File extension matching the container actually used for recording
+    pub file_extension: &'static str,
+    /// Path to the video file
+    pub file_path: PathBuf,
+}
+
+/// Defines a file produced by capturing - a frame or a video.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CaptureArtifact {
+    /// Path to the file
+    pub file_path: PathBuf,
+    /// File size in bytes
+    pub size: u64,
+    /// Local time of the capture start in RFC 3339 format
+    pub timestamp: String,
+    /// File format, which is the file extension
+    pub format: String,
+    /// Frame width in pixels
+    pub width: u32,
+    /// Frame height in pixels
+    pub height: u32,
+    /// Codec actually used for recording, only for videos
+    pub codec: Option<Codec>,
+    /// Duration in seconds of the written frames, only for videos
+    pub duration: Option<f64>,
+    /// Number of written frames, only for videos
+    pub frame_count: Option<u64>,
+}
+
+/// Defines a status of a video capturing object.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CaptureStatus {
+    /// Capturing device name
+    pub device_name: String,
+    /// Whether the capturing device is in use by preview, recording or motion monitoring
+    pub busy: bool,
+    /// Quality metrics of the latest analyzed frame
+    pub quality: Option<QualityMetrics>,
+}
+
+/// Defines frame timing skew of a video recording - how late frames are read from the device
+/// relative to their scheduled time on the recording timeline.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FrameSkew {
+    /// Capturing device name
+    pub device_name: String,
+    /// Number of frames read
+    pub frames: u64,
+    /// Mean lateness in milliseconds
+    pub mean_ms: f64,
+    /// Maximum lateness in milliseconds
+    pub max_ms: f64,
+}
+
+const MAX_MOTION_EVENTS: usize = 100;
+const MAX_RECONNECT_EVENTS: usize = 100;
+/// Session name used in file names until a session is set.
+const DEFAULT_SESSION: &str = "default";
+/// File name of the frame saved periodically in headless preview.
+const SNAPSHOT_FILE_NAME: &str = "latest.jpg";
+/// Age after which the frame of a running grabber is not streamed anymore.
+const LIVE_FRAME_TIMEOUT: Duration = Duration::from_secs(1);
 
 /// Defines a video capturing object - configuration and OpenCV structures.
 pub struct Capture {
@@ -51,165 +166,452 @@ pub struct Capture {
     pub config: Config,
     capture: Arc<Mutex<VideoCapture>>,
     writer: Arc<Mutex<Option<VideoWriter>>>,
+    device_name: String,
+    frame_number: Arc<AtomicU64>,
+    motion_events: Arc<Mutex<VecDeque<MotionEvent>>>,
+    quality: Arc<Mutex<QualityAnalyzer>>,
+    reconnect_events: Arc<Mutex<VecDeque<ReconnectEvent>>>,
+    is_grabbing: Arc<AtomicBool>,
+    skew: Arc<Mutex<FrameSkew>>,
+    live_frame: Arc<Mutex<LiveFrame>>,
+    event_bus: Arc<EventBus>,
+    recorder: Option<JoinHandle<Vec<CaptureArtifact>>>,
+    monitor: Option<JoinHandle<()>>,
+    previewer: Option<JoinHandle<()>>,
+    namer: FileNamer,
+}
+
+impl CaptureArtifact {
+    fn new(file_path: &Path, timestamp: DateTime<Local>, frame_size: (u32, u32)) -> Self {
+        CaptureArtifact {
+            file_path: file_path.to_path_buf(),
+            size: fs::metadata(file_path).map_or(0, |metadata| metadata.len()),
+            timestamp: timestamp.to_rfc3339(),
+            format: file_path
+                .extension()
+                .map(|extension| extension.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            width: frame_size.0,
+            height: frame_size.1,
+            codec: None,
+            duration: None,
+            frame_count: None,
+        }
+    }
+
+    fn video(
+        output: &VideoOutput,
+        timestamp: DateTime<Local>,
+        frame_size: (u32, u32),
+        frame_count: u64,
+        fps: u32,
+    ) -> Self {
+        CaptureArtifact {
+            codec: Some(output.codec),
+            duration: Some(frame_count as f64 / fps as f64),
+            frame_count: Some(frame_count),
+            ..Self::new(&output.file_path, timestamp, frame_size)
+        }
+    }
+}
+
+impl FrameSkew {
+    fn new(device_name: &str) -> Self {
+        FrameSkew {
+            device_name: device_name.to_string(),
+            frames: 0,
+            mean_ms: 0.0,
+            max_ms: 0.0,
+        }
+    }
+
+    fn add(&mut self, lateness: f64) {
+        let lateness_ms = lateness * 1000.0;
+        self.frames += 1;
+        self.mean_ms += (lateness_ms - self.mean_ms) / self.frames as f64;
+        self.max_ms = self.max_ms.max(lateness_ms);
+    }
 }
 
 impl Capture {
     /// Constructor for a video capturing object.
     pub fn new(conf: &Config) -> Result<Self, CaptureError> {
+        Self::create(conf, None)
+    }
+
+    /// Constructor for a video capturing object with an event subscriber,
+    /// which also receives the initialization event.
+    pub fn new_with_events(
+        conf: &Config,
+        events: Sender<CaptureEvent>,
+    ) -> Result<Self, CaptureError> {
+        Self::create(conf, Some(events))
+    }
+
+    fn create(conf: &Config, events: Option<Sender<CaptureEvent>>) -> Result<Self, CaptureError> {
         debug!("create capture instance");
         let config = conf.clone();
+        if let Err(reason) = config
+            .validate()
+            .and_then(|_| codec::check_writer_options(&config.codec_params))
+        {
+            let err = CaptureError::InvalidConfig(reason);
+            error!("{}", err);
+            return Err(err);
+        }
         let device_id = config.device_id;
         let data_dir = &config.data_dir;
         if file::create_dir(data_dir).is_err() {
-            let err = CaptureError::CreateFileDirectory(data_dir.clone());
+            let err = CaptureError::CreateFileDirectory(data_dir.display().to_string());
             error!("{}", err);
             return Err(err);
         }
 
-        let mut instance = Self::new_capture(device_id)?;
-
-        if !instance.is_opened()? {
-            let err = CaptureError::DeviceOpen(device_id.to_string());
-            error!("{}", err);
-            return Err(err);
+        let container = config.video_container();
+        if !container.supports(config.codec) {
+            warn!(
+                "codec {:?} is not expected to work with container {:?}",
+                config.codec, container
+            );
         }
 
-        Self::capture_set_fps(&mut instance, conf.fps)?;
-        Self::capture_verify_fps(&instance, conf.fps)?;
-        Self::capture_set_frame_size(&mut instance, (conf.frame_width, conf.frame_height))?;
-        Self::capture_verify_frame_size(&instance, (conf.frame_width, conf.frame_height))?;
+        let instance = Self::open_device(&config)?;
 
+        let device_name = Self::get_device_name(device_id);
+        let event_bus = EventBus::new(&device_name);
+        if let Some(events) = events {
+            event_bus.add_subscriber(events);
+        }
+        event_bus.publish(CaptureEventKind::Initialized { device_id });
         Ok(Self {
+            device_name,
             config,
             capture: Arc::new(Mutex::new(instance)),
             writer: Arc::new(Mutex::new(None)),
+            frame_number: Arc::new(AtomicU64::new(0)),
+            motion_events: Arc::new(Mutex::new(VecDeque::new())),
+            quality: Arc::new(Mutex::new(QualityAnalyzer::default())),
+            reconnect_events: Arc::new(Mutex::new(VecDeque::new())),
+            is_grabbing: Arc::new(AtomicBool::new(false)),
+            skew: Arc::new(Mutex::new(FrameSkew::new(&device_name))),
+            live_frame: Arc::new(Mutex::new(LiveFrame::default())),
+            event_bus: Arc::new(event_bus),
+            recorder: None,
+            monitor: None,
+            previewer: None,
+            namer: FileNamer::new(&device_name, DEFAULT_SESSION),
         })
     }
 
     /// Destructor for a video capturing object.
     pub fn dispose(&mut self) -> Result<(), CaptureError> {
         debug!("dispose capture instance");
-        self.capture
-            .lock()
-            .unwrap()
-            .release()
-            .map_err(CaptureError::from)
+        // recording and motion monitoring are stopped first, so that their video files are completed
+        self.stop_grab_video()?;
+        self.stop_motion_monitor()?;
+        self.capture.lock().unwrap().release()?;
+        self.event_bus.publish(CaptureEventKind::Disposed);
+        Ok(())
     }
 
-    /// Previews captured video stream.
+    /// Previews captured video stream, until the preview window is closed or Esc is pressed.
     pub fn preview(&mut self) -> Result<(), CaptureError> {
         debug!("preview streaming");
-        if IS_GRABBING.load(Ordering::Relaxed) {
-            let err = CaptureError::ResourceBusy;
-            error!("{}", err);
-            return Err(err);
+        if self.is_grabbing.load(Ordering::Relaxed) {
+            return Err(self.busy_error("preview"));
         }
-        IS_GRABBING.store(true, Ordering::Relaxed);
+        self.is_grabbing.store(true, Ordering::Relaxed);
 
-        let device_name = String::from("unknown capture device");
-        let window = match device::enumerate_capture_devices() {
-            Ok(devices) => devices
-                .get(self.config.device_id as usize)
-                .cloned()
-                .unwrap_or(device_name),
-            Err(_) => device_name,
-        };
-        highgui::named_window(&window, highgui::WINDOW_AUTOSIZE)?;
-        loop {
-            let mut frame = Mat::default();
-            self.capture.lock().unwrap().read(&mut frame)?;
-            if frame.size()?.width > 0 {
-                highgui::imshow(&window, &frame)?;
-            }
-            let key = highgui::wait_key(10)?;
-            if key == 27 || highgui::get_window_property(&window, highgui::WND_PROP_VISIBLE)? < 1.0 {
-                break;
-            }
+        let result = Self::preview_stream(
+            &self.capture,
+            &self.config,
+            &self.device_name,
+            &self.frame_number,
+            &self.live_frame,
+            &self.event_bus,
+            &self.is_grabbing,
+        );
+        self.is_grabbing.store(false, Ordering::Relaxed);
+        result
+    }
+
+    /// Starts previewing captured video stream in a separate thread, in a window
+    /// or headless, the preview is stopped as a video stream or when its window is closed.
+    pub fn start_preview(&mut self, mode: PreviewMode) -> Result<(), CaptureError> {
+        debug!("start preview thread");
+        if self.is_grabbing.load(Ordering::Relaxed) {
+            return Err(self.busy_error("preview"));
         }
-        IS_GRABBING.store(false, Ordering::Relaxed);
+        self.is_grabbing.store(true, Ordering::Relaxed);
+
+        let capture = Arc::clone(&self.capture);
+        let config = self.config.clone();
+        let device_name = self.device_name.clone();
+        let frame_number = Arc::clone(&self.frame_number);
+        let live_frame = Arc::clone(&self.live_frame);
+        let event_bus = Arc::clone(&self.event_bus);
+        let is_grabbing = Arc::clone(&self.is_grabbing);
+
+        self.previewer = Some(thread::spawn(move || {
+            debug!("spawn {} preview thread", mode.name());
+            let preview = match mode {
+                PreviewMode::Window => Self::preview_stream,
+                PreviewMode::Headless => Self::preview_headless,
+            };
+            if let Err(err) = preview(
+                &capture,
+                &config,
+                &device_name,
+                &frame_number,
+                &live_frame,
+                &event_bus,
+                &is_grabbing,
+            ) {
+                Self::report_error(&event_bus, &err);
+            }
+            is_grabbing.store(false, Ordering::Relaxed);
+        }));
         Ok(())
     }
 
     /// Saves captured frame as a file with a given path / name.
-    pub fn grab_frame_to_file(&mut self, file_path: &str) -> Result<bool, CaptureError> {
-        debug!("grab frame to file '{}'", file_path);
-        if IS_GRABBING.load(Ordering::Relaxed) {
-            let err = CaptureError::ResourceBusy;
-            error!("{}", err);
-            return Err(err);
+    pub fn grab_frame_to_file(
+        &mut self,
+        file_path: &Path,
+    ) -> Result<CaptureArtifact, CaptureError> {
+        debug!("grab frame to file '{}'", file_path.display());
+        if self.is_grabbing.load(Ordering::Relaxed) {
+            return Err(self.busy_error("grab frame"));
         }
-        IS_GRABBING.store(true, Ordering::Relaxed);
+        self.is_grabbing.store(true, Ordering::Relaxed);
 
-        let mut frame = Mat::default();
-        let success = self.capture.lock().unwrap().read(&mut frame)?;
-        if !success || frame.empty() {
-            let err = CaptureError::GrabFrame;
-            error!("{}", err);
-            return Err(err);
+        let timestamp = Local::now();
+        let result = self
+            .read_converted_frame(self.config.color_mode.for_encoding())
+            .and_then(|frame| {
+                let mut params = core::Vector::default();
+                params.push(imgcodecs::IMWRITE_PNG_COMPRESSION);
+                params.push(0);
+                // written under a partial name and renamed, so that no truncated file is left
+                let partial_path = file::partial_path(file_path);
+                let written = imgcodecs::imwrite(&partial_path.to_string_lossy(), &frame, &params)
+                    .map_err(CaptureError::from)
+                    .and_then(|written| {
+                        if written && file::complete(file_path).is_ok() {
+                            Ok(())
+                        } else {
+                            let path = file_path.display().to_string();
+                            Err(CaptureError::CreateFileDirectory(path))
+                        }
+                    });
+                if let Err(err) = written {
+                    let _ = fs::remove_file(&partial_path);
+                    error!("{}", err);
+                    return Err(err);
+                }
+                let frame_size = (frame.cols() as u32, frame.rows() as u32);
+                Ok(CaptureArtifact::new(file_path, timestamp, frame_size))
+            });
+        self.is_grabbing.store(false, Ordering::Relaxed);
+        if let Ok(artifact) = &result {
+            self.event_bus.publish(CaptureEventKind::FrameGrabbed {
+                file_path: artifact.file_path.clone(),
+            });
         }
-        let mut params = core::Vector::default();
-        params.push(imgcodecs::IMWRITE_PNG_COMPRESSION);
-        params.push(0);
-        imgcodecs::imwrite(file_path, &frame, &params)?;
-        IS_GRABBING.store(false, Ordering::Relaxed);
-        Ok(success)
+        result
+    }
+
+    /// Reads captured frame into memory, converted to the configured color mode.
+    pub fn read_frame(&mut self) -> Result<Mat, CaptureError> {
+        debug!("read frame");
+        if self.is_grabbing.load(Ordering::Relaxed) {
+            return Err(self.busy_error("read frame"));
+        }
+        self.is_grabbing.store(true, Ordering::Relaxed);
+
+        let result = self.read_converted_frame(self.config.color_mode);
+        self.is_grabbing.store(false, Ordering::Relaxed);
+        result
     }
 
     /// Saves captured frame as a file with the default file name.
-    /// The file path is defined in the configuration, the file name is `<timestamp>.png`.
-    pub fn grab_frame(&mut self) -> Result<bool, CaptureError> {
-        let file_path = format!("{}\\{}", &self.config.data_dir, file::get_name("png"));
+    /// The file path is defined in the configuration, the file name is `<timestamp>.png`
+    /// unless a file name template is configured.
+    pub fn grab_frame(&mut self) -> Result<CaptureArtifact, CaptureError> {
+        self.grab_frame_named(&Naming::default())
+    }
+
+    /// Saves captured frame as a file named by a given naming,
+    /// which takes precedence over the configured naming.
+    pub fn grab_frame_named(&mut self, naming: &Naming) -> Result<CaptureArtifact, CaptureError> {
+        let naming = self.naming(naming)?;
+        let file_path = self
+            .namer
+            .file_path(&self.config.data_dir, &naming, "png")?;
         self.grab_frame_to_file(&file_path)
     }
 
     /// Starts capturing video stream to a file with a given path / name.
-    pub fn start_grab_video_to_file(&mut self, file_path: &str) -> Result<bool, CaptureError> {
-        debug!("grab video to file '{}'", file_path);
-        if IS_GRABBING.load(Ordering::Relaxed) {
-            let err = CaptureError::ResourceBusy;
-            error!("{}", err);
-            return Err(err);
-        }
-        IS_GRABBING.store(true, Ordering::Relaxed);
-
-        let capture = Arc::clone(&self.capture);
-        let writer = Arc::clone(&self.writer);
+    /// If the configured codec cannot be opened, the codec fallback chain is tried in order
+    /// and the file extension is changed to match the container of the codec actually used.
+    /// If the stream is lost, the device is reopened and recording resumes into a new file
+    /// with the default file name.
+    pub fn start_grab_video_to_file(
+        &mut self,
+        file_path: &Path,
+    ) -> Result<CaptureArtifact, CaptureError> {
+        self.start_grab_video_to_file_at(file_path, Instant::now())
+    }
 
+    /// Starts capturing video stream to a file with a given path / name,
+    /// with frames sampled on a timeline beginning at a given start time.
+    /// Sharing the start time synchronizes frame sampling of several devices.
+    pub fn start_grab_video_to_file_at(
+        &mut self,
+        file_path: &Path,
+        start_time: Instant,
+    ) -> Result<CaptureArtifact, CaptureError> {
+        debug!("grab video to file '{}'", file_path.display());
         let fps = self.get_fps()?;
-        let frame_size = self.get_frame_size()?;
-        let file_path = file_path.to_string();
-
-        let fourcc = self.config.codec.fourcc()?;
-        let mut writer_loc = writer.lock().unwrap();
-        *writer_loc = Some(VideoWriter::new(
-            &file_path,
-            fourcc,
+        let frame_size = self.config.transform.output_size(self.get_frame_size()?);
+
+        if self.is_grabbing.load(Ordering::Relaxed) {
+            return Err(self.busy_error("grab video"));
+        }
+        self.is_grabbing.store(true, Ordering::Relaxed);
+
+        let (new_writer, output) = match Self::open_writer(
+            &self.config,
+            file_path,
             fps as f64,
             Size::new(frame_size.0 as i32, frame_size.1 as i32),
-            true,
-        )?);
-        drop(writer_loc);
+        ) {
+            Ok(result) => result,
+            Err(err) => {
+                self.is_grabbing.store(false, Ordering::Relaxed);
+                error!("{}", err);
+                return Err(err);
+            }
+        };
 
-        thread::spawn(move || {
+        let capture = Arc::clone(&self.capture);
+        let writer = Arc::clone(&self.writer);
+        *writer.lock().unwrap() = Some(new_writer);
+        let config = self.config.clone();
+        let color_mode = config.color_mode.for_encoding();
+        let device_name = self.device_name.clone();
+        let frame_number = Arc::clone(&self.frame_number);
+        let quality = Arc::clone(&self.quality);
+        let reconnect_events = Arc::clone(&self.reconnect_events);
+        let is_grabbing = Arc::clone(&self.is_grabbing);
+        let writer_size = Size::new(frame_size.0 as i32, frame_size.1 as i32);
+        let skew = Arc::clone(&self.skew);
+        *skew.lock().unwrap() = FrameSkew::new(&device_name);
+        let live_frame = Arc::clone(&self.live_frame);
+        let event_bus = Arc::clone(&self.event_bus);
+        let namer = self.namer.clone();
+        let start_delay = start_time.saturating_duration_since(Instant::now());
+        let timestamp = Local::now() + chrono::Duration::from_std(start_delay).unwrap_or_default();
+        let artifact = CaptureArtifact::video(&output, timestamp, frame_size, 0, fps);
+        event_bus.publish(CaptureEventKind::RecordingStarted {
+            file_path: output.file_path.clone(),
+        });
+
+        self.recorder = Some(thread::spawn(move || {
             debug!("spawn grabber thread");
 
-            let start_time = Instant::now();
+            let mut output = output;
+            let mut timestamp = timestamp;
+            let mut segments = Vec::new();
+            let mut written_frames: u64 = 0;
             let mut frame_count: u64 = 0;
+            let mut stream_watchdog = StreamWatchdog::new(&config.watchdog);
 
-            while IS_GRABBING.load(Ordering::Relaxed) {
+            while is_grabbing.load(Ordering::Relaxed) {
                 let elapsed = start_time.elapsed();
                 let target_frame_count = (elapsed.as_secs_f64() * fps as f64).floor() as u64;
 
                 if frame_count < target_frame_count {
                     let mut frame = Mat::default();
-                    if capture.lock().unwrap().read(&mut frame).unwrap() {
-                        writer
-                            .lock()
-                            .unwrap()
-                            .as_mut()
-                            .unwrap()
-                            .write(&frame)
-                            .unwrap();
+                    let success =
+                        capture.lock().unwrap().read(&mut frame).unwrap_or(false) && !frame.empty();
+                    if let Some(reason) = stream_watchdog.check(success.then_some(&frame)) {
+                        if let Some(closed) = writer.lock().unwrap().take() {
+                            Self::finish_video(closed, &output, &event_bus);
+                            segments.push(CaptureArtifact::video(
+                                &output,
+                                timestamp,
+                                frame_size,
+                                written_frames,
+                                fps,
+                            ));
+                        }
+                        let Some(attempts) = Self::reconnect(
+                            &capture,
+                            &config,
+                            &reconnect_events,
+                            &event_bus,
+                            &reason,
+                            &is_grabbing,
+                        ) else {
+                            break;
+                        };
+                        let opened = Self::default_video_path(&config, &namer).and_then(|path| {
+                            Self::open_writer(&config, &path, fps as f64, writer_size)
+                        });
+                        match opened {
+                            Ok((new_writer, new_output)) => {
+                                *writer.lock().unwrap() = Some(new_writer);
+                                output = new_output;
+                                timestamp = Local::now();
+                                written_frames = 0;
+                            }
+                            Err(err) => {
+                                Self::report_error(&event_bus, &err);
+                                is_grabbing.store(false, Ordering::Relaxed);
+                                break;
+                            }
+                        }
+                        let event = ReconnectEvent::new(
+                            ReconnectEventKind::Reconnected,
+                            &reason,
+                            attempts,
+                            Some(output.file_path.clone()),
+                        );
+                        Self::push_reconnect_event(&reconnect_events, &event_bus, event);
+                        event_bus.publish(CaptureEventKind::SegmentRotated {
+                            file_path: output.file_path.clone(),
+                        });
+                        stream_watchdog.reset();
+                        frame_count =
+                            (start_time.elapsed().as_secs_f64() * fps as f64).floor() as u64;
+                        continue;
+                    }
+                    if success {
+                        let scheduled = (frame_count + 1) as f64 / fps as f64;
+                        let lateness = start_time.elapsed().as_secs_f64() - scheduled;
+                        skew.lock().unwrap().add(lateness);
+                        let _ = Self::analyze_quality(&quality, &config, &event_bus, &frame);
+                        let context = OverlayContext {
+                            device_name: &device_name,
+                            frame_number: frame_number.fetch_add(1, Ordering::Relaxed) + 1,
+                        };
+                        let overlay = config.overlay.enabled.then_some(&context);
+                        match Self::process_frame(&config, frame, color_mode, overlay) {
+                            Ok(frame) => {
+                                live_frame
+                                    .lock()
+                                    .unwrap()
+                                    .publish(&frame, config.stream.frame_interval());
+                                let written =
+                                    writer.lock().unwrap().as_mut().unwrap().write(&frame);
+                                match written {
+                                    Ok(()) => written_frames += 1,
+                                    Err(err) => Self::report_error(&event_bus, &err),
+                                }
+                            }
+                            Err(err) => Self::report_error(&event_bus, &err),
+                        }
                     }
                     frame_count += 1;
                 } else {
@@ -217,31 +619,293 @@ impl Capture {
                 }
             }
 
-            let mut writer_lock = writer.lock().unwrap();
-            *writer_lock = None;
-        });
+            // the last segment is already finished if reconnecting has failed
+            if let Some(closed) = writer.lock().unwrap().take() {
+                Self::finish_video(closed, &output, &event_bus);
+                segments.push(CaptureArtifact::video(
+                    &output,
+                    timestamp,
+                    frame_size,
+                    written_frames,
+                    fps,
+                ));
+            }
+            event_bus.publish(CaptureEventKind::RecordingStopped {
+                file_path: output.file_path.clone(),
+            });
+            segments
+        }));
 
-        Ok(true)
+        Ok(artifact)
     }
 
     /// Starts capturing video stream to a file with the default file name.
-    /// The file path is defined in the configuration, the file name is `<timestamp>.<codec_extention>`.
-    pub fn start_grab_video(&mut self) -> Result<bool, CaptureError> {
-        let file_path = format!(
-            "{}\\{}",
-            &self.config.data_dir,
-            file::get_name(self.config.codec.file_extension())
-        );
+    /// The file path is defined in the configuration, the file name is `<timestamp>.<container_extention>`
+    /// unless a file name template is configured.
+    pub fn start_grab_video(&mut self) -> Result<CaptureArtifact, CaptureError> {
+        self.start_grab_video_named(&Naming::default())
+    }
+
+    /// Starts capturing video stream to a file named by a given naming,
+    /// which takes precedence over the configured naming.
+    pub fn start_grab_video_named(
+        &mut self,
+        naming: &Naming,
+    ) -> Result<CaptureArtifact, CaptureError> {
+        let naming = self.naming(naming)?;
+        let extension = self.config.video_container().file_extension();
+        let file_path = self
+            .namer
+            .file_path(&self.config.data_dir, &naming, extension)?;
         self.start_grab_video_to_file(&file_path)
     }
 
-    /// Stops capturing video stream.
-    pub fn stop_grab_video(&mut self) -> Result<(), CaptureError> {
+    /// Sets the session name used by the `{session}` placeholder of file name templates.
+    pub fn set_session(&mut self, session: &str) {
+        self.namer.set_session(session);
+    }
+
+    /// Stops capturing video stream or preview.
+    /// Waits for the video files to be finalized and returns them, if a recording was running.
+    /// A recording continued in new files after reconnections returns a file per segment.
+    pub fn stop_grab_video(&mut self) -> Result<Vec<CaptureArtifact>, CaptureError> {
         debug!("stop grabber thread");
-        IS_GRABBING.store(false, Ordering::Relaxed);
+        self.is_grabbing.store(false, Ordering::Relaxed);
+        // the preview thread clears the grabbing flag when it ends, so it must not outlive the stop
+        if let Some(previewer) = self.previewer.take() {
+            let _ = previewer.join();
+        }
+        Ok(self
+            .recorder
+            .take()
+            .and_then(|recorder| recorder.join().ok())
+            .unwrap_or_default())
+    }
+
+    /// Starts monitoring video stream for motion.
+    /// Motion events are logged and stored, and if recording is enabled, video is saved to a file
+    /// with the default file name from the pre-roll before the motion to the post-roll after it.
+    pub fn start_motion_monitor(&mut self) -> Result<(), CaptureError> {
+        debug!("start motion monitor");
+        let fps = self.get_fps()?;
+        let frame_size = self.config.transform.output_size(self.get_frame_size()?);
+        let frame_size = Size::new(frame_size.0 as i32, frame_size.1 as i32);
+        let mut detector = MotionDetector::new(&self.config.motion)?;
+
+        if self.is_grabbing.load(Ordering::Relaxed) {
+            return Err(self.busy_error("monitor motion"));
+        }
+        self.is_grabbing.store(true, Ordering::Relaxed);
+
+        let capture = Arc::clone(&self.capture);
+        let config = self.config.clone();
+        let color_mode = config.color_mode.for_encoding();
+        let device_name = self.device_name.clone();
+        let frame_number = Arc::clone(&self.frame_number);
+        let events = Arc::clone(&self.motion_events);
+        let quality = Arc::clone(&self.quality);
+        let reconnect_events = Arc::clone(&self.reconnect_events);
+        let live_frame = Arc::clone(&self.live_frame);
+        let event_bus = Arc::clone(&self.event_bus);
+        let is_grabbing = Arc::clone(&self.is_grabbing);
+        let namer = self.namer.clone();
+
+        self.monitor = Some(thread::spawn(move || {
+            debug!("spawn motion monitor thread");
+
+            let pre_roll_len = (config.motion.pre_roll * fps as f64).ceil() as usize;
+            let post_roll = Duration::from_secs_f64(config.motion.post_roll);
+            let mut pre_roll: VecDeque<Mat> = VecDeque::with_capacity(pre_roll_len + 1);
+            let mut recording: Option<(VideoWriter, VideoOutput)> = None;
+            let mut in_motion = false;
+            let mut last_motion = Instant::now();
+            let mut last_area = 0.0;
+            let mut stream_watchdog = StreamWatchdog::new(&config.watchdog);
+
+            while is_grabbing.load(Ordering::Relaxed) {
+                let mut frame = Mat::default();
+                let success =
+                    capture.lock().unwrap().read(&mut frame).unwrap_or(false) && !frame.empty();
+                if let Some(reason) = stream_watchdog.check(success.then_some(&frame)) {
+                    if in_motion {
+                        in_motion = false;
+                        let file_path = recording.take().map(|(writer, output)| {
+                            Self::finish_video(writer, &output, &event_bus)
+                        });
+                        let event =
+                            MotionEvent::new(MotionEventKind::Stopped, last_area, file_path);
+                        Self::push_motion_event(&events, &event_bus, event);
+                    }
+                    pre_roll.clear();
+                    let Some(attempts) = Self::reconnect(
+                        &capture,
+                        &config,
+                        &reconnect_events,
+                        &event_bus,
+                        &reason,
+                        &is_grabbing,
+                    ) else {
+                        break;
+                    };
+                    let event = ReconnectEvent::new(
+                        ReconnectEventKind::Reconnected,
+                        &reason,
+                        attempts,
+                        None,
+                    );
+                    Self::push_reconnect_event(&reconnect_events, &event_bus, event);
+                    stream_watchdog.reset();
+                    continue;
+                }
+                if !success {
+                    thread::sleep(Duration::from_millis(1000_u64 / fps.max(1) as u64));
+                    continue;
+                }
+                let _ = Self::analyze_quality(&quality, &config, &event_bus, &frame);
+                let area = detector.detect(&frame).unwrap_or(0.0);
+
+                if area >= config.motion.min_area {
+                    last_motion = Instant::now();
+                    last_area = area;
+                    if !in_motion {
+                        in_motion = true;
+                        if config.motion.record {
+                            let opened =
+                                Self::default_video_path(&config, &namer).and_then(|path| {
+                                    Self::open_writer(&config, &path, fps as f64, frame_size)
+                                });
+                            match opened {
+                                Ok((mut writer, output)) => {
+                                    for frame in pre_roll.drain(..) {
+                                        let _ = writer.write(&frame);
+                                    }
+                                    recording = Some((writer, output));
+                                }
+                                Err(err) => Self::report_error(&event_bus, &err),
+                            }
+                        }
+                        let file_path = recording.as_ref().map(|r| r.1.file_path.clone());
+                        let event = MotionEvent::new(MotionEventKind::Started, area, file_path);
+                        Self::push_motion_event(&events, &event_bus, event);
+                    }
+                } else if in_motion && last_motion.elapsed() >= post_roll {
+                    in_motion = false;
+                    let file_path = recording
+                        .take()
+                        .map(|(writer, output)| Self::finish_video(writer, &output, &event_bus));
+                    let event = MotionEvent::new(MotionEventKind::Stopped, last_area, file_path);
+                    Self::push_motion_event(&events, &event_bus, event);
+                }
+
+                let context = OverlayContext {
+                    device_name: &device_name,
+                    frame_number: frame_number.fetch_add(1, Ordering::Relaxed) + 1,
+                };
+                let overlay = config.overlay.enabled.then_some(&context);
+                let frame = match Self::process_frame(&config, frame, color_mode, overlay) {
+                    Ok(frame) => frame,
+                    Err(err) => {
+                        Self::report_error(&event_bus, &err);
+                        continue;
+                    }
+                };
+                live_frame
+                    .lock()
+                    .unwrap()
+                    .publish(&frame, config.stream.frame_interval());
+                if let Some((writer, _)) = recording.as_mut() {
+                    let _ = writer.write(&frame);
+                } else if pre_roll_len > 0 {
+                    pre_roll.push_back(frame);
+                    if pre_roll.len() > pre_roll_len {
+                        pre_roll.pop_front();
+                    }
+                }
+            }
+
+            if in_motion {
+                let file_path = recording
+                    .take()
+                    .map(|(writer, output)| Self::finish_video(writer, &output, &event_bus));
+                let event = MotionEvent::new(MotionEventKind::Stopped, last_area, file_path);
+                Self::push_motion_event(&events, &event_bus, event);
+            }
+        }));
+
+        Ok(())
+    }
+
+    /// Stops monitoring video stream for motion.
+    /// Waits for the monitor to finish, so that its last motion event is stored.
+    pub fn stop_motion_monitor(&mut self) -> Result<(), CaptureError> {
+        debug!("stop motion monitor thread");
+        self.is_grabbing.store(false, Ordering::Relaxed);
+        if let Some(monitor) = self.monitor.take() {
+            let _ = monitor.join();
+        }
         Ok(())
     }
 
+    /// Gets current status.
+    pub fn status(&self) -> CaptureStatus {
+        CaptureStatus {
+            device_name: self.device_name.clone(),
+            busy: self.is_grabbing.load(Ordering::Relaxed),
+            quality: self.quality.lock().unwrap().latest().cloned(),
+        }
+    }
+
+    /// Gets the latest motion events, oldest first.
+    pub fn motion_events(&self) -> Vec<MotionEvent> {
+        self.motion_events.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Gets frame timing skew of the current or the last video recording.
+    pub fn frame_skew(&self) -> FrameSkew {
+        self.skew.lock().unwrap().clone()
+    }
+
+    /// Gets the latest reconnection events, oldest first.
+    pub fn reconnect_events(&self) -> Vec<ReconnectEvent> {
+        self.reconnect_events
+            .lock()
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Subscribes to capture events, the subscription ends when the receiver is dropped.
+    pub fn subscribe(&self) -> Receiver<CaptureEvent> {
+        self.event_bus.subscribe()
+    }
+
+    /// Gets the latest frame for live streaming without interfering with a running grabber.
+    /// While the device is busy, the latest frame of the grabber is used, otherwise a frame
+    /// is read from the device at most at the maximum stream frame rate. Frames read for
+    /// streaming do not advance the frame counter and are not quality checked.
+    /// Returns `None` if the grabber has not provided a frame recently.
+    pub fn live_frame(&self) -> Result<Option<Mat>, CaptureError> {
+        let busy = self.is_grabbing.load(Ordering::Relaxed);
+        let max_age = if busy {
+            LIVE_FRAME_TIMEOUT
+        } else {
+            self.config.stream.frame_interval()
+        };
+        if let Some(frame) = self.live_frame.lock().unwrap().latest(max_age) {
+            return Ok(Some(frame));
+        }
+        if busy {
+            return Ok(None);
+        }
+        let frame = self.read_live_frame(self.config.color_mode.for_encoding())?;
+        self.live_frame
+            .lock()
+            .unwrap()
+            .publish(&frame, self.config.stream.frame_interval());
+        Ok(Some(frame))
+    }
+
     /// Gets current FPS value.
     pub fn get_fps(&self) -> Result<u32, CaptureError> {
         Self::capture_get_fps(&self.capture.lock().unwrap()).map_err(CaptureError::from)
@@ -260,8 +924,13 @@ impl Capture {
         Self::capture_verify_fps(&self.capture.lock().unwrap(), fps).map_err(CaptureError::from)
     }
 
-    /// Sets frame size.
+    /// Sets frame size, which has to fit the configured transformation.
     pub fn set_frame_size(&mut self, size: (u32, u32)) -> Result<bool, CaptureError> {
+        if let Err(reason) = self.config.transform.validate(size) {
+            let err = CaptureError::InvalidConfig(reason);
+            error!("{}", err);
+            return Err(err);
+        }
         Self::capture_set_frame_size(&mut (self.capture.lock().unwrap()), size)
             .map_err(CaptureError::from)?;
         self.config.frame_width = size.0;
@@ -270,6 +939,480 @@ impl Capture {
             .map_err(CaptureError::from)
     }
 
+    /// Gets current value of a camera control.
+    pub fn get_control(&self, control: Control) -> Result<Option<f64>, CaptureError> {
+        Self::capture_get_control(&self.capture.lock().unwrap(), control)
+            .map_err(CaptureError::from)
+    }
+
+    /// Sets camera control value.
+    pub fn set_control(&mut self, control: Control, value: f64) -> Result<bool, CaptureError> {
+        let mut controls = Controls::default();
+        controls.set(control, Some(value));
+        self.set_controls(&controls).map(|effective| {
+            effective
+                .get(control)
+                .is_some_and(|actual| Self::control_matches(control, value, actual))
+        })
+    }
+
+    /// Gets current values of all camera controls.
+    pub fn get_controls(&self) -> Result<Controls, CaptureError> {
+        let capture = self.capture.lock().unwrap();
+        let mut controls = Controls::default();
+        for control in Control::ALL {
+            controls.set(control, Self::capture_get_control(&capture, control)?);
+        }
+        Ok(controls)
+    }
+
+    /// Sets camera control values, controls that are not set are left unchanged.
+    /// Returns effective values of all camera controls.
+    pub fn set_controls(&mut self, controls: &Controls) -> Result<Controls, CaptureError> {
+        if let Err(reason) = controls.validate() {
+            let err = CaptureError::InvalidConfig(reason);
+            error!("{}", err);
+            return Err(err);
+        }
+        {
+            let mut capture = self.capture.lock().unwrap();
+            Self::capture_set_controls(&mut capture, controls)?;
+            Self::capture_verify_controls(&capture, controls)?;
+        }
+        self.config.controls.merge(controls);
+        self.get_controls()
+    }
+
+    /// Creates a preset from current frame rate, frame size and values of all camera controls.
+    pub fn snapshot_preset(&self, name: &str) -> Result<Preset, CaptureError> {
+        let (width, height) = self.get_frame_size()?;
+        Ok(Preset {
+            fps: Some(self.get_fps()?),
+            frame_width: Some(width),
+            frame_height: Some(height),
+            ..Preset::new(name, self.get_controls()?)
+        })
+    }
+
+    /// Applies frame rate, frame size and camera control values of a preset.
+    /// Frame rate and frame size cannot be changed while capturing.
+    /// Returns effective values of all camera controls.
+    pub fn apply_preset(&mut self, preset: &Preset) -> Result<Controls, CaptureError> {
+        debug!("apply preset '{}'", preset.name);
+        let changes_format = preset.fps.is_some() || preset.frame_size().is_some();
+        if changes_format && self.is_grabbing.load(Ordering::Relaxed) {
+            return Err(self.busy_error("apply preset"));
+        }
+        if let Some(fps) = preset.fps {
+            self.set_fps(fps)?;
+        }
+        if let Some(size) = preset.frame_size() {
+            self.set_frame_size(size)?;
+        }
+        let controls = self.set_controls(&preset.controls)?;
+        self.config.preset = Some(preset.name.clone());
+        Ok(controls)
+    }
+
+    fn open_device(config: &Config) -> Result<VideoCapture, CaptureError> {
+        let device_id = config.device_id;
+        let mut instance = Self::new_capture(device_id)?;
+
+        if !instance.is_opened()? {
+            let err = CaptureError::DeviceOpen(device_id.to_string());
+            error!("{}", err);
+            return Err(err);
+        }
+
+        Self::capture_set_fps(&mut instance, config.fps)?;
+        Self::capture_verify_fps(&instance, config.fps)?;
+        Self::capture_set_frame_size(&mut instance, (config.frame_width, config.frame_height))?;
+        Self::capture_verify_frame_size(&instance, (config.frame_width, config.frame_height))?;
+        Self::capture_set_controls(&mut instance, &config.controls)?;
+        Self::capture_verify_controls(&instance, &config.controls)?;
+        let frame_size = Self::capture_get_frame_size(&instance)?;
+        if let Err(reason) = config.transform.validate(frame_size) {
+            let err = CaptureError::InvalidConfig(reason);
+            error!("{}", err);
+            return Err(err);
+        }
+        let raw_frames = config.color_mode.is_passthrough();
+        if raw_frames && !Self::capture_disable_rgb_conversion(&mut instance)? {
+            let err = CaptureError::InvalidConfig(format!(
+                "device {} does not deliver single-channel raw frames",
+                device_id
+            ));
+            error!("{}", err);
+            return Err(err);
+        }
+        Ok(instance)
+    }
+
+    /// Releases and reopens the capturing device with backoff until it succeeds,
+    /// capturing is stopped, the attempts are exhausted or the reopened device does not fit
+    /// the configuration, the latter two also stop capturing.
+    /// Returns the number of attempts if the device has been reopened.
+    fn reconnect(
+        capture: &Mutex<VideoCapture>,
+        config: &Config,
+        events: &Mutex<VecDeque<ReconnectEvent>>,
+        event_bus: &EventBus,
+        reason: &str,
+        is_grabbing: &AtomicBool,
+    ) -> Option<u32> {
+        warn!("capture stream lost: {}", reason);
+        let event = ReconnectEvent::new(ReconnectEventKind::Disconnected, reason, 0, None);
+        Self::push_reconnect_event(events, event_bus, event);
+        let _ = capture.lock().unwrap().release();
+
+        let mut attempt = 0;
+        let mut failure = None;
+        while is_grabbing.load(Ordering::Relaxed) {
+            if config
+                .watchdog
+                .max_attempts
+                .is_some_and(|max| attempt >= max)
+            {
+                failure = Some(reason.to_string());
+                break;
+            }
+            attempt += 1;
+            let deadline = Instant::now() + config.watchdog.backoff(attempt);
+            while is_grabbing.load(Ordering::Relaxed) && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(100));
+            }
+            debug!("reconnect attempt {}", attempt);
+            match Self::open_device(config) {
+                Ok(instance) => {
+                    *capture.lock().unwrap() = instance;
+                    return Some(attempt);
+                }
+                // retrying does not help if the device does not fit the configuration
+                Err(err @ CaptureError::InvalidConfig(_)) => {
+                    failure = Some(err.to_string());
+                    break;
+                }
+                Err(err) => debug!("reconnect attempt {} failed: {}", attempt, err),
+            }
+        }
+        if let Some(reason) = failure {
+            let event = ReconnectEvent::new(ReconnectEventKind::Failed, &reason, attempt, None);
+            Self::push_reconnect_event(events, event_bus, event);
+            is_grabbing.store(false, Ordering::Relaxed);
+        }
+        None
+    }
+
+    fn open_writer(
+        config: &Config,
+        file_path: &Path,
+        fps: f64,
+        frame_size: Size,
+    ) -> Result<(VideoWriter, VideoOutput), CaptureError> {
+        let container = config.video_container();
+        let dir = file_path.parent().unwrap_or(Path::new(""));
+        let name = file_path.file_stem().unwrap_or_default().to_string_lossy();
+        let mut candidates = vec![(config.codec, container, file_path.to_path_buf())];
+        for &codec in &config.codec_fallback {
+            let container = if container.supports(codec) {
+                container
+            } else {
+                codec.default_container()
+            };
+            // a fallback file gets a numeric suffix instead of overwriting an existing file
+            let path = FileNamer::join(dir, &Naming::default(), &name, container.file_extension())?;
+            candidates.push((codec, container, path));
+        }
+
+        for (codec, container, path) in candidates {
+            let params = if config.codec_params.validate(codec).is_ok() {
+                config.codec_params
+            } else {
+                CodecParams::default()
+            };
+            match codec::open_writer(
+                &file::partial_path(&path).to_string_lossy(),
+                codec,
+                &params,
+                fps,
+                frame_size,
+                config.color_mode.is_color(),
+            ) {
+                Ok(writer) if writer.is_opened().unwrap_or(false) => {
+                    debug!("video writer opened with codec {:?}", codec);
+                    let output = VideoOutput {
+                        codec,
+                        file_extension: container.file_extension(),
+                        file_path: path,
+                    };
+                    return Ok((writer, output));
+                }
+                Ok(_) => warn!("cannot open video writer with codec {:?}", codec),
+                Err(err) => warn!("cannot open video writer with codec {:?}: {}", codec, err),
+            }
+            let _ = fs::remove_file(file::partial_path(&path));
+        }
+        Err(CaptureError::VideoWriterOpen(
+            file_path.display().to_string(),
+        ))
+    }
+
+    fn preview_stream(
+        capture: &Mutex<VideoCapture>,
+        config: &Config,
+        device_name: &str,
+        frame_number: &AtomicU64,
+        live_frame: &Mutex<LiveFrame>,
+        _event_bus: &EventBus,
+        is_grabbing: &AtomicBool,
+    ) -> Result<(), CaptureError> {
+        let window = device_name.to_string();
+        let color_mode = config.color_mode.for_encoding();
+        highgui::named_window(&window, highgui::WINDOW_AUTOSIZE)?;
+        while is_grabbing.load(Ordering::Relaxed) {
+            let mut frame = Mat::default();
+            capture.lock().unwrap().read(&mut frame)?;
+            if frame.size()?.width > 0 {
+                let context = OverlayContext {
+                    device_name,
+                    frame_number: frame_number.fetch_add(1, Ordering::Relaxed) + 1,
+                };
+                let overlay = config.overlay.preview.then_some(&context);
+                let frame = Self::process_frame(config, frame, color_mode, overlay)?;
+                live_frame
+                    .lock()
+                    .unwrap()
+                    .publish(&frame, config.stream.frame_interval());
+                highgui::imshow(&window, &frame)?;
+            }
+            let key = highgui::wait_key(10)?;
+            if key == 27 || highgui::get_window_property(&window, highgui::WND_PROP_VISIBLE)? < 1.0 {
+                break;
+            }
+        }
+        highgui::destroy_window(&window)?;
+        Ok(())
+    }
+
+    fn preview_headless(
+        capture: &Mutex<VideoCapture>,
+        config: &Config,
+        device_name: &str,
+        frame_number: &AtomicU64,
+        live_frame: &Mutex<LiveFrame>,
+        event_bus: &EventBus,
+        is_grabbing: &AtomicBool,
+    ) -> Result<(), CaptureError> {
+        let color_mode = config.color_mode.for_encoding();
+        let snapshot_path = config.data_dir.join(SNAPSHOT_FILE_NAME);
+        let mut last_snapshot: Option<Instant> = None;
+        while is_grabbing.load(Ordering::Relaxed) {
+            let mut frame = Mat::default();
+            if !capture.lock().unwrap().read(&mut frame)? || frame.empty() {
+                thread::sleep(Duration::from_millis(10));
+                continue;
+            }
+            let context = OverlayContext {
+                device_name,
+                frame_number: frame_number.fetch_add(1, Ordering::Relaxed) + 1,
+            };
+            let overlay = config.overlay.preview.then_some(&context);
+            let frame = Self::process_frame(config, frame, color_mode, overlay)?;
+            live_frame
+                .lock()
+                .unwrap()
+                .publish(&frame, config.stream.frame_interval());
+
+            let Some(interval) = config.stream.snapshot_interval else {
+                continue;
+            };
+            if last_snapshot.is_some_and(|time| time.elapsed().as_secs_f64() < interval) {
+                continue;
+            }
+            last_snapshot = Some(Instant::now());
+            let jpeg = stream::encode_jpeg(&frame, config.stream.max_quality)?;
+            // written next to the snapshot and renamed, so that readers never see a partial file,
+            // the preview goes on serving the live stream if the snapshot cannot be written
+            if fs::write(file::partial_path(&snapshot_path), jpeg)
+                .and_then(|_| file::complete(&snapshot_path))
+                .is_err()
+            {
+                let err = CaptureError::CreateFileDirectory(snapshot_path.display().to_string());
+                Self::report_error(event_bus, &err);
+            }
+        }
+        Ok(())
+    }
+
+    fn read_converted_frame(&self, color_mode: ColorMode) -> Result<Mat, CaptureError> {
+        let mut frame = Mat::default();
+        let success = self.capture.lock().unwrap().read(&mut frame)?;
+        if !success || frame.empty() {
+            let err = CaptureError::GrabFrame;
+            error!("{}", err);
+            return Err(err);
+        }
+        let issues = Self::analyze_quality(&self.quality, &self.config, &self.event_bus, &frame)?;
+        if self.config.quality.fail_grab && !issues.is_empty() {
+            let err = CaptureError::FrameQuality(issues);
+            error!("{}", err);
+            return Err(err);
+        }
+        let context = OverlayContext {
+            device_name: &self.device_name,
+            frame_number: self.frame_number.fetch_add(1, Ordering::Relaxed) + 1,
+        };
+        let overlay = self.config.overlay.enabled.then_some(&context);
+        Self::process_frame(&self.config, frame, color_mode, overlay).map_err(CaptureError::from)
+    }
+
+    /// Reads a frame for streaming, the overlay shows the number of the last grabbed frame.
+    fn read_live_frame(&self, color_mode: ColorMode) -> Result<Mat, CaptureError> {
+        let mut frame = Mat::default();
+        let success = self.capture.lock().unwrap().read(&mut frame)?;
+        if !success || frame.empty() {
+            return Err(CaptureError::GrabFrame);
+        }
+        let context = OverlayContext {
+            device_name: &self.device_name,
+            frame_number: self.frame_number.load(Ordering::Relaxed),
+        };
+        let overlay = self.config.overlay.enabled.then_some(&context);
+        Self::process_frame(&self.config, frame, color_mode, overlay).map_err(CaptureError::from)
+    }
+
+    fn process_frame(
+        config: &Config,
+        mut frame: Mat,
+        color_mode: ColorMode,
+        overlay: Option<&OverlayContext>,
+    ) -> Result<Mat> {
+        for mask in &config.masks {
+            mask.apply(&mut frame)?;
+        }
+        let mut frame = config.transform.apply(frame)?;
+        if let Some(context) = overlay {
+            config.overlay.apply(&mut frame, context)?;
+        }
+        color_mode.convert(frame)
+    }
+
+    /// Analyzes frame quality, a warning is logged and published
+    /// each time the set of threshold violations changes.
+    fn analyze_quality(
+        quality: &Mutex<QualityAnalyzer>,
+        config: &Config,
+        event_bus: &EventBus,
+        frame: &Mat,
+    ) -> Result<Vec<QualityIssue>> {
+        if !config.quality.enabled {
+            return Ok(Vec::new());
+        }
+        let mut quality = quality.lock().unwrap();
+        let previous_issues = quality
+            .latest()
+            .map(|metrics| metrics.issues.clone())
+            .unwrap_or_default();
+        let metrics = quality.analyze(frame, &config.quality)?;
+        if metrics.issues != previous_issues && !metrics.issues.is_empty() {
+            warn!("frame quality issues {:?}: {:?}", metrics.issues, metrics);
+            event_bus.publish(CaptureEventKind::QualityWarning(metrics.clone()));
+        }
+        Ok(metrics.issues.clone())
+    }
+
+    fn default_video_path(config: &Config, namer: &FileNamer) -> Result<PathBuf, CaptureError> {
+        let extension = config.video_container().file_extension();
+        namer.file_path(&config.data_dir, &config.naming, extension)
+    }
+
+    /// Gets the configured naming merged with a given naming, which takes precedence.
+    fn naming(&self, naming: &Naming) -> Result<Naming, CaptureError> {
+        let mut merged = self.config.naming.clone();
+        merged.merge(naming);
+        if let Err(reason) = merged.validate() {
+            let err = CaptureError::InvalidConfig(reason);
+            error!("{}", err);
+            return Err(err);
+        }
+        Ok(merged)
+    }
+
+    fn push_motion_event(
+        events: &Mutex<VecDeque<MotionEvent>>,
+        event_bus: &EventBus,
+        event: MotionEvent,
+    ) {
+        info!(
+            "motion {:?} at {}, area {:.3}",
+            event.kind, event.timestamp, event.area
+        );
+        event_bus.publish(CaptureEventKind::Motion(event.clone()));
+        let mut events = events.lock().unwrap();
+        if events.len() == MAX_MOTION_EVENTS {
+            events.pop_front();
+        }
+        events.push_back(event);
+    }
+
+    fn push_reconnect_event(
+        events: &Mutex<VecDeque<ReconnectEvent>>,
+        event_bus: &EventBus,
+        event: ReconnectEvent,
+    ) {
+        info!(
+            "capture stream {:?} at {} after {} attempts: {}",
+            event.kind, event.timestamp, event.attempts, event.reason
+        );
+        event_bus.publish(CaptureEventKind::Reconnect(event.clone()));
+        let mut events = events.lock().unwrap();
+        if events.len() == MAX_RECONNECT_EVENTS {
+            events.pop_front();
+        }
+        events.push_back(event);
+    }
+
+    fn busy_error(&self, operation: &str) -> CaptureError {
+        let err = CaptureError::ResourceBusy;
+        error!("cannot {}: {}", operation, err);
+        self.event_bus.publish(CaptureEventKind::Busy {
+            operation: operation.to_string(),
+        });
+        err
+    }
+
+    /// Releases a video writer and renames the video file from its partial name to its final name.
+    fn finish_video(writer: VideoWriter, output: &VideoOutput, event_bus: &EventBus) -> PathBuf {
+        drop(writer);
+        if let Err(err) = file::complete(&output.file_path) {
+            let err = format!(
+                "cannot complete video file '{}': {}",
+                output.file_path.display(),
+                err
+            );
+            Self::report_error(event_bus, &err);
+        }
+        output.file_path.clone()
+    }
+
+    fn report_error(event_bus: &EventBus, err: &dyn std::fmt::Display) {
+        error!("{}", err);
+        event_bus.publish(CaptureEventKind::Error {
+            message: err.to_string(),
+        });
+    }
+
+    fn get_device_name(device_id: u32) -> String {
+        let device_name = String::from("unknown capture device");
+        match device::enumerate_capture_devices() {
+            Ok(devices) => devices
+                .get(device_id as usize)
+                .cloned()
+                .unwrap_or(device_name),
+            Err(_) => device_name,
+        }
+    }
+
     fn new_capture(device_id: u32) -> Result<VideoCapture> {
         VideoCapture::new(device_id as i32, CAP_MSMF)
     }
@@ -303,6 +1446,80 @@ impl Capture {
         Ok(width_set && height_set)
     }
 
+    fn capture_disable_rgb_conversion(capture: &mut VideoCapture) -> Result<bool, opencv::Error> {
+        capture.set(CAP_PROP_CONVERT_RGB, 0.0)?;
+        let convert_rgb = capture.get(CAP_PROP_CONVERT_RGB)?;
+        debug!("set rgb conversion: {convert_rgb}");
+        if convert_rgb != 0.0 {
+            return Ok(false);
+        }
+        // MSMF and DShow may report the conversion disabled and still deliver BGR frames
+        let mut frame = Mat::default();
+        capture.read(&mut frame)?;
+        debug!("raw frame channels: {}", frame.channels());
+        Ok(frame.channels() == 1)
+    }
+
+    fn capture_get_control(
+        capture: &VideoCapture,
+        control: Control,
+    ) -> Result<Option<f64>, opencv::Error> {
+        let backend = Self::capture_get_backend(capture)?;
+        let value = capture.get(control.property())?;
+        debug!("get control {:?}: {}", control, value);
+        Ok(control.from_property(backend, value))
+    }
+
+    fn capture_get_backend(capture: &VideoCapture) -> Result<i32, opencv::Error> {
+        capture.get(CAP_PROP_BACKEND).map(|backend| backend as i32)
+    }
+
+    fn capture_set_controls(
+        capture: &mut VideoCapture,
+        controls: &Controls,
+    ) -> Result<bool, opencv::Error> {
+        let backend = Self::capture_get_backend(capture)?;
+        let mut success = true;
+        for control in Control::ALL {
+            if let Some(value) = controls.get(control) {
+                let value = control.to_property(backend, value);
+                success &= capture.set(control.property(), value)?;
+                debug!("set control {:?}: {}", control, value);
+            }
+        }
+        Ok(success)
+    }
+
+    fn capture_verify_controls(
+        capture: &VideoCapture,
+        expected: &Controls,
+    ) -> Result<bool, opencv::Error> {
+        let mut success = true;
+        for control in Control::ALL {
+            if let Some(expected_value) = expected.get(control) {
+                let actual_value = Self::capture_get_control(capture, control)?;
+                if !actual_value
+                    .is_some_and(|actual| Self::control_matches(control, expected_value, actual))
+                {
+                    warn!(
+                        "control {:?} mismatch: expected {}, actual {:?}",
+                        control, expected_value, actual_value
+                    );
+                    success = false;
+                }
+            }
+        }
+        Ok(success)
+    }
+
+    fn control_matches(control: Control, expected: f64, actual: f64) -> bool {
+        if control.is_switch() {
+            (expected != 0.0) == (actual != 0.0)
+        } else {
+            (expected - actual).abs() <= 1e-3 * expected.abs().max(1.0)
+        }
+    }
+
     fn capture_verify_fps(
         capture: &VideoCapture,
         expected_fps: u32,
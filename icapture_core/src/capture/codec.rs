@@ -1,7 +1,23 @@
-//! Provides FourCC values and file extentions for different video codecs.
+//! Provides FourCC values and file extentions for different video codecs and containers.
 
-use opencv::{videoio, Result};
+use log::debug;
+use opencv::{
+    core::{Size, Vector},
+    prelude::*,
+    videoio, Result,
+};
 use serde::{Deserialize, Serialize};
+use std::{
+    env, fs,
+    sync::{Mutex, OnceLock},
+};
+
+const FFMPEG_WRITER_OPTIONS: &str = "OPENCV_FFMPEG_WRITER_OPTIONS";
+
+/// FFmpeg writer options of the process, set once by [`init_writer_options`].
+static WRITER_OPTIONS: OnceLock<Option<String>> = OnceLock::new();
+/// Serializes opening of video writers with setting the FFmpeg writer options.
+static WRITER_LOCK: Mutex<()> = Mutex::new(());
 
 /// Defines supported video codecs.
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
@@ -12,28 +28,353 @@ pub enum Codec {
     MJPG,
     /// Windows Media Video
     WMV3,
+    /// Xvid MPEG-4 Part 2
+    XVID,
+    /// WebM VP8
+    VP8,
+    /// WebM VP9
+    VP9,
+    /// FFmpeg lossless video codec
+    FFV1,
+    /// Huffman lossless video codec
+    HFYU,
+    /// Uncompressed frames
+    RAW,
+}
+
+/// Defines supported video containers.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Container {
+    /// MPEG-4 Part 14
+    MP4,
+    /// Audio Video Interleave
+    AVI,
+    /// Matroska
+    MKV,
+    /// WebM
+    WEBM,
+    /// Windows Media
+    WMV,
+}
+
+/// Defines the result of probing a codec and container combination.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct CodecSupport {
+    /// Probed video codec
+    pub codec: Codec,
+    /// Probed video container
+    pub container: Container,
+    /// Whether the local OpenCV build can open a video writer for the combination
+    pub supported: bool,
+}
+
+/// Defines video codec parameters, codec defaults are used for parameters that are not set.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CodecParams {
+    /// Encoding quality in percent (1-100)
+    pub quality: Option<u32>,
+    /// Target bitrate in kbit/s
+    pub bitrate: Option<u32>,
+    /// Keyframe interval in frames
+    pub keyframe_interval: Option<u32>,
 }
 
 impl Codec {
+    /// All supported video codecs.
+    pub const ALL: [Codec; 9] = [
+        Codec::H264,
+        Codec::MJPG,
+        Codec::WMV3,
+        Codec::XVID,
+        Codec::VP8,
+        Codec::VP9,
+        Codec::FFV1,
+        Codec::HFYU,
+        Codec::RAW,
+    ];
+
     /// Gets a FourCC value of a specific video codec.
-    /// 
+    ///
     /// # Errors
-    /// 
+    ///
     /// Returns the corresponding OpenCV error in case of failure.
     pub fn fourcc(&self) -> Result<i32> {
         match self {
-            Codec::H264 => videoio::VideoWriter::fourcc('H', '2', '6', '4'), // OK            
+            Codec::H264 => videoio::VideoWriter::fourcc('H', '2', '6', '4'), // OK
             Codec::MJPG => videoio::VideoWriter::fourcc('M', 'J', 'P', 'G'), // NOK at 60 fps
             Codec::WMV3 => videoio::VideoWriter::fourcc('W', 'M', 'V', '3'), // NOK at 60 fps
+            Codec::XVID => videoio::VideoWriter::fourcc('X', 'V', 'I', 'D'),
+            Codec::VP8 => videoio::VideoWriter::fourcc('V', 'P', '8', '0'),
+            Codec::VP9 => videoio::VideoWriter::fourcc('V', 'P', '9', '0'),
+            Codec::FFV1 => videoio::VideoWriter::fourcc('F', 'F', 'V', '1'),
+            Codec::HFYU => videoio::VideoWriter::fourcc('H', 'F', 'Y', 'U'),
+            Codec::RAW => Ok(0), // zero FourCC makes OpenCV write uncompressed frames
+        }
+    }
+
+    /// Gets a default container for a specific video codec.
+    pub fn default_container(&self) -> Container {
+        match self {
+            Codec::H264 => Container::MP4,
+            Codec::MJPG => Container::AVI,
+            Codec::WMV3 => Container::WMV,
+            Codec::XVID => Container::AVI,
+            Codec::VP8 => Container::WEBM,
+            Codec::VP9 => Container::WEBM,
+            Codec::FFV1 => Container::MKV,
+            Codec::HFYU => Container::AVI,
+            Codec::RAW => Container::AVI,
         }
     }
 
-    /// Gets a file extention for a specific video codec.
+    /// Gets a file extention of the default container for a specific video codec.
+    pub fn file_extension(&self) -> &'static str {
+        self.default_container().file_extension()
+    }
+
+    /// Checks whether a specific video codec is lossless.
+    pub fn is_lossless(&self) -> bool {
+        matches!(self, Codec::FFV1 | Codec::HFYU | Codec::RAW)
+    }
+
+    /// Checks whether a specific video codec compresses every frame independently.
+    pub fn is_intra_only(&self) -> bool {
+        matches!(self, Codec::MJPG) || self.is_lossless()
+    }
+}
+
+impl CodecParams {
+    /// Validates codec parameters against a specific video codec.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the first invalid parameter.
+    pub fn validate(&self, codec: Codec) -> std::result::Result<(), String> {
+        if let Some(quality) = self.quality {
+            if codec.is_lossless() {
+                return Err(format!("codec {:?} does not support quality", codec));
+            }
+            if !(1..=100).contains(&quality) {
+                return Err(format!("quality {} is out of range 1-100", quality));
+            }
+        }
+        if let Some(bitrate) = self.bitrate {
+            if codec.is_lossless() {
+                return Err(format!("codec {:?} does not support bitrate", codec));
+            }
+            if bitrate == 0 {
+                return Err("bitrate must be positive".to_string());
+            }
+        }
+        if let Some(keyframe_interval) = self.keyframe_interval {
+            if codec.is_intra_only() {
+                return Err(format!(
+                    "codec {:?} does not support keyframe interval",
+                    codec
+                ));
+            }
+            if keyframe_interval == 0 {
+                return Err("keyframe interval must be positive".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    fn writer_params(&self, is_color: bool) -> Vector<i32> {
+        let mut params = Vector::default();
+        params.push(videoio::VIDEOWRITER_PROP_HW_ACCELERATION);
+        params.push(videoio::VIDEO_ACCELERATION_NONE);
+        params.push(videoio::VIDEOWRITER_PROP_IS_COLOR);
+        params.push(is_color as i32);
+        if let Some(quality) = self.quality {
+            params.push(videoio::VIDEOWRITER_PROP_QUALITY);
+            params.push(quality as i32);
+        }
+        params
+    }
+
+    fn ffmpeg_options(&self) -> Option<String> {
+        let mut options = Vec::new();
+        if let Some(bitrate) = self.bitrate {
+            options.push(format!("b;{}", bitrate as u64 * 1000));
+        }
+        if let Some(keyframe_interval) = self.keyframe_interval {
+            options.push(format!("g;{}", keyframe_interval));
+        }
+        if options.is_empty() {
+            None
+        } else {
+            Some(options.join("|"))
+        }
+    }
+}
+
+impl Container {
+    /// All supported video containers.
+    pub const ALL: [Container; 5] = [
+        Container::MP4,
+        Container::AVI,
+        Container::MKV,
+        Container::WEBM,
+        Container::WMV,
+    ];
+
+    /// Gets a file extention for a specific video container.
     pub fn file_extension(&self) -> &'static str {
         match self {
-            Codec::H264 => "mp4",
-            Codec::MJPG => "avi",
-            Codec::WMV3 => "wmv",
+            Container::MP4 => "mp4",
+            Container::AVI => "avi",
+            Container::MKV => "mkv",
+            Container::WEBM => "webm",
+            Container::WMV => "wmv",
         }
     }
+
+    /// Checks whether a video codec can be stored in a specific video container.
+    /// The check is static, use [`probe_support`] to verify the local OpenCV build.
+    pub fn supports(&self, codec: Codec) -> bool {
+        match self {
+            Container::MP4 => matches!(codec, Codec::H264 | Codec::MJPG | Codec::XVID | Codec::VP9),
+            Container::AVI => !matches!(codec, Codec::VP8 | Codec::VP9),
+            Container::MKV => codec != Codec::WMV3,
+            Container::WEBM => matches!(codec, Codec::VP8 | Codec::VP9),
+            Container::WMV => codec == Codec::WMV3,
+        }
+    }
+}
+
+/// Probes every codec and container combination by opening a video writer for it.
+/// Temporary files are created in the system temporary directory and removed afterwards.
+pub fn probe_support() -> Vec<CodecSupport> {
+    let dir = std::env::temp_dir();
+    let mut result = Vec::new();
+    for codec in Codec::ALL {
+        for container in Container::ALL {
+            let file_path = dir.join(format!(
+                "icapture_probe_{:?}.{}",
+                codec,
+                container.file_extension()
+            ));
+            let supported = probe(&file_path.to_string_lossy(), codec).unwrap_or(false);
+            debug!("probe {:?} in {:?}: {}", codec, container, supported);
+            let _ = fs::remove_file(&file_path);
+            result.push(CodecSupport {
+                codec,
+                container,
+                supported,
+            });
+        }
+    }
+    result
+}
+
+/// Sets bitrate and keyframe interval of all video writers of the process.
+///
+/// OpenCV only takes them from the `OPENCV_FFMPEG_WRITER_OPTIONS` environment variable
+/// (`VIDEOWRITER_PROP_KEY_INTERVAL` applies to raw video encapsulation only), and the
+/// environment cannot be changed safely while other threads read it. Call this once
+/// at startup before spawning threads, later calls are ignored. Bitrate and keyframe
+/// interval cannot be set per video writer.
+pub fn init_writer_options(params: &CodecParams) {
+    let _lock = WRITER_LOCK.lock().unwrap();
+    WRITER_OPTIONS.get_or_init(|| {
+        let options = params.ffmpeg_options();
+        if let Some(options) = &options {
+            debug!("ffmpeg writer options: {}", options);
+            env::set_var(FFMPEG_WRITER_OPTIONS, options);
+        }
+        options
+    });
+}
+
+/// Checks that bitrate and keyframe interval of codec parameters, if set,
+/// are the ones set by [`init_writer_options`], since they cannot be set per video writer.
+///
+/// # Errors
+///
+/// Returns a description of the mismatch.
+pub(crate) fn check_writer_options(params: &CodecParams) -> std::result::Result<(), String> {
+    let options = WRITER_OPTIONS.get().cloned().flatten();
+    let requested = params.ffmpeg_options();
+    if requested.is_some() && requested != options {
+        return Err(format!(
+            "bitrate and keyframe interval are set at startup only to {}, cannot use {}",
+            options.as_deref().unwrap_or("defaults"),
+            requested.as_deref().unwrap_or_default()
+        ));
+    }
+    Ok(())
+}
+
+/// Opens a video writer for a specific video codec with given codec parameters.
+/// Bitrate and keyframe interval are taken from the options set by [`init_writer_options`].
+pub(crate) fn open_writer(
+    file_path: &str,
+    codec: Codec,
+    params: &CodecParams,
+    fps: f64,
+    frame_size: Size,
+    is_color: bool,
+) -> Result<videoio::VideoWriter> {
+    let _lock = WRITER_LOCK.lock().unwrap();
+    videoio::VideoWriter::new_1(
+        file_path,
+        codec.fourcc()?,
+        fps,
+        frame_size,
+        &params.writer_params(is_color),
+    )
+}
+
+fn probe(file_path: &str, codec: Codec) -> Result<bool> {
+    let mut writer =
+        videoio::VideoWriter::new(file_path, codec.fourcc()?, 30.0, Size::new(64, 64), true)?;
+    let opened = writer.is_opened()?;
+    writer.release()?;
+    Ok(opened)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_container_supports_codec() {
+        for codec in Codec::ALL {
+            assert!(codec.default_container().supports(codec), "{:?}", codec);
+        }
+    }
+
+    #[test]
+    fn test_codec_serialization() {
+        for codec in Codec::ALL {
+            let json = serde_json::to_string(&codec).unwrap();
+            assert_eq!(json, format!("\"{:?}\"", codec));
+            assert_eq!(serde_json::from_str::<Codec>(&json).unwrap(), codec);
+        }
+        for container in Container::ALL {
+            let json = serde_json::to_string(&container).unwrap();
+            assert_eq!(serde_json::from_str::<Container>(&json).unwrap(), container);
+        }
+        assert_eq!(Codec::FFV1.file_extension(), "mkv");
+        assert!(serde_json::from_str::<Codec>("\"AV1\"").is_err());
+    }
+
+    #[test]
+    fn test_codec_params_validation() {
+        let params = CodecParams {
+            quality: Some(80),
+            bitrate: Some(8000),
+            keyframe_interval: Some(60),
+        };
+        assert!(params.validate(Codec::H264).is_ok());
+        assert!(params.validate(Codec::MJPG).is_err());
+        assert!(params.validate(Codec::FFV1).is_err());
+        let params = CodecParams {
+            quality: Some(0),
+            ..Default::default()
+        };
+        assert!(params.validate(Codec::MJPG).is_err());
+    }
 }
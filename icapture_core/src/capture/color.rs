@@ -0,0 +1,54 @@
+//! Provides output color modes for captured frames.
+
+use opencv::{imgproc, prelude::*, Result};
+use serde::{Deserialize, Serialize};
+
+/// Defines supported output color modes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum ColorMode {
+    /// Three channels in blue, green, red order
+    #[default]
+    BGR,
+    /// Three channels in red, green, blue order.
+    /// Image and video encoders expect BGR, so files are still written in BGR order.
+    RGB,
+    /// Single channel grayscale
+    GRAY,
+    /// Single channel raw Bayer data as delivered by the device
+    BAYER,
+}
+
+impl ColorMode {
+    /// Checks whether frames in a specific color mode have three channels.
+    pub fn is_color(&self) -> bool {
+        matches!(self, ColorMode::BGR | ColorMode::RGB)
+    }
+
+    /// Checks whether a specific color mode requires the device to skip RGB conversion.
+    pub fn is_passthrough(&self) -> bool {
+        *self == ColorMode::BAYER
+    }
+
+    /// Gets the color mode to use when passing frames to image and video encoders.
+    pub(crate) fn for_encoding(&self) -> ColorMode {
+        match self {
+            ColorMode::RGB => ColorMode::BGR,
+            mode => *mode,
+        }
+    }
+
+    /// Converts a captured BGR frame to a specific color mode.
+    pub(crate) fn convert(&self, frame: Mat) -> Result<Mat> {
+        let code = match self {
+            ColorMode::BGR | ColorMode::BAYER => return Ok(frame),
+            ColorMode::RGB => imgproc::COLOR_BGR2RGB,
+            ColorMode::GRAY => imgproc::COLOR_BGR2GRAY,
+        };
+        if frame.channels() != 3 {
+            return Ok(frame);
+        }
+        let mut converted = Mat::default();
+        imgproc::cvt_color_def(&frame, &mut converted, code)?;
+        Ok(converted)
+    }
+}
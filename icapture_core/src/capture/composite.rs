@@ -0,0 +1,177 @@
+//! Provides composing frames from several devices into one grid frame.
+
+use opencv::{
+    core::{self, Point, Rect, Scalar, Size},
+    imgproc,
+    prelude::*,
+    Result,
+};
+use serde::{Deserialize, Serialize};
+
+const FONT_FACE: i32 = imgproc::FONT_HERSHEY_SIMPLEX;
+const MARGIN: i32 = 8;
+
+/// Defines a grid composite of frames from several devices.
+/// Each device frame is letterboxed into its cell, keeping its aspect ratio,
+/// and devices running at a different frame rate are sampled for their latest frame.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Composite {
+    /// Number of grid columns, the smallest square-ish grid fitting all devices if not set,
+    /// e.g. 2x1 for two devices and 2x2 for three or four
+    pub columns: Option<u32>,
+    /// Grid cell width in pixels
+    pub cell_width: u32,
+    /// Grid cell height in pixels
+    pub cell_height: u32,
+    /// Frame rate of the composite video, the frame rate of the first device if not set
+    pub fps: Option<u32>,
+    /// Draw the device name in each cell
+    pub labels: bool,
+    /// Color of letterbox bars and empty cells as RGB
+    pub background: [u8; 3],
+}
+
+impl Default for Composite {
+    fn default() -> Self {
+        Composite {
+            columns: None,
+            cell_width: 960,
+            cell_height: 540,
+            fps: None,
+            labels: true,
+            background: [0, 0, 0],
+        }
+    }
+}
+
+impl Composite {
+    /// Validates the composite settings.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the first invalid setting.
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        if self.columns == Some(0) {
+            return Err("composite columns must be positive".to_string());
+        }
+        if self.cell_width == 0 || self.cell_height == 0 {
+            return Err("composite cell must not be empty".to_string());
+        }
+        if self.fps == Some(0) {
+            return Err("composite fps must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    /// Gets the grid size as columns and rows for a number of devices.
+    pub fn grid(&self, devices: usize) -> (u32, u32) {
+        let devices = devices.max(1) as u32;
+        let columns = self
+            .columns
+            .unwrap_or_else(|| (devices as f64).sqrt().ceil() as u32)
+            .min(devices);
+        (columns, devices.div_ceil(columns))
+    }
+
+    /// Gets the composite frame size for a number of devices.
+    pub fn frame_size(&self, devices: usize) -> Size {
+        let (columns, rows) = self.grid(devices);
+        Size::new(
+            (self.cell_width * columns) as i32,
+            (self.cell_height * rows) as i32,
+        )
+    }
+
+    /// Composes device frames into a grid frame, cells of missing frames are left empty.
+    pub(crate) fn compose(&self, frames: &[Option<Mat>], labels: &[String]) -> Result<Mat> {
+        let (columns, _) = self.grid(frames.len());
+        let size = self.frame_size(frames.len());
+        let background = Scalar::new(
+            self.background[2] as f64,
+            self.background[1] as f64,
+            self.background[0] as f64,
+            0.0,
+        );
+        let mut grid =
+            Mat::new_rows_cols_with_default(size.height, size.width, core::CV_8UC3, background)?;
+
+        for (index, frame) in frames.iter().enumerate() {
+            let cell = Rect::new(
+                (index as u32 % columns * self.cell_width) as i32,
+                (index as u32 / columns * self.cell_height) as i32,
+                self.cell_width as i32,
+                self.cell_height as i32,
+            );
+            if let Some(frame) = frame.as_ref().filter(|frame| !frame.empty()) {
+                self.letterbox(frame, &mut grid, cell)?;
+            }
+            if let Some(label) = labels.get(index).filter(|_| self.labels) {
+                imgproc::put_text(
+                    &mut grid,
+                    label,
+                    Point::new(cell.x + MARGIN, cell.y + MARGIN * 4),
+                    FONT_FACE,
+                    1.0,
+                    Scalar::all(255.0),
+                    2,
+                    imgproc::LINE_AA,
+                    false,
+                )?;
+            }
+        }
+        Ok(grid)
+    }
+
+    fn letterbox(&self, frame: &Mat, grid: &mut Mat, cell: Rect) -> Result<()> {
+        let frame_size = frame.size()?;
+        let scale = f64::min(
+            cell.width as f64 / frame_size.width as f64,
+            cell.height as f64 / frame_size.height as f64,
+        );
+        let scaled_size = Size::new(
+            ((frame_size.width as f64 * scale).round() as i32).clamp(1, cell.width),
+            ((frame_size.height as f64 * scale).round() as i32).clamp(1, cell.height),
+        );
+        let mut scaled = Mat::default();
+        imgproc::resize(
+            frame,
+            &mut scaled,
+            scaled_size,
+            0.0,
+            0.0,
+            imgproc::INTER_AREA,
+        )?;
+        if scaled.channels() == 1 {
+            let mut color = Mat::default();
+            imgproc::cvt_color_def(&scaled, &mut color, imgproc::COLOR_GRAY2BGR)?;
+            scaled = color;
+        }
+        let target = Rect::new(
+            cell.x + (cell.width - scaled_size.width) / 2,
+            cell.y + (cell.height - scaled_size.height) / 2,
+            scaled_size.width,
+            scaled_size.height,
+        );
+        scaled.copy_to(&mut Mat::roi_mut(grid, target)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grid() {
+        let composite = Composite::default();
+        assert_eq!(composite.grid(2), (2, 1));
+        assert_eq!(composite.grid(3), (2, 2));
+        assert_eq!(composite.grid(4), (2, 2));
+        assert_eq!(composite.frame_size(2), Size::new(1920, 540));
+        let composite = Composite {
+            columns: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(composite.grid(3), (1, 3));
+    }
+}
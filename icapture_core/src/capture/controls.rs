@@ -0,0 +1,247 @@
+//! Provides camera controls - exposure, gain, white balance, focus and image adjustments.
+
+use opencv::videoio::*;
+use serde::{Deserialize, Serialize};
+
+/// Defines camera controls.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Control {
+    /// Automatic exposure
+    AutoExposure,
+    /// Exposure value, usually log2 of the exposure time in seconds
+    Exposure,
+    /// Sensor gain
+    Gain,
+    /// White balance temperature in Kelvin
+    WhiteBalance,
+    /// Automatic focus
+    AutoFocus,
+    /// Focus distance
+    Focus,
+    /// Image brightness
+    Brightness,
+    /// Image contrast
+    Contrast,
+    /// Image saturation
+    Saturation,
+    /// Image sharpness
+    Sharpness,
+    /// Optical or digital zoom
+    Zoom,
+}
+
+/// Defines camera control values, controls that are not set are left unchanged.
+/// Value ranges depend on the capturing device.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Controls {
+    /// Automatic exposure
+    pub auto_exposure: Option<bool>,
+    /// Exposure value, usually log2 of the exposure time in seconds
+    pub exposure: Option<f64>,
+    /// Sensor gain
+    pub gain: Option<f64>,
+    /// White balance temperature in Kelvin
+    pub white_balance: Option<f64>,
+    /// Automatic focus
+    pub auto_focus: Option<bool>,
+    /// Focus distance
+    pub focus: Option<f64>,
+    /// Image brightness
+    pub brightness: Option<f64>,
+    /// Image contrast
+    pub contrast: Option<f64>,
+    /// Image saturation
+    pub saturation: Option<f64>,
+    /// Image sharpness
+    pub sharpness: Option<f64>,
+    /// Optical or digital zoom
+    pub zoom: Option<f64>,
+}
+
+impl Control {
+    /// All controls, automatic modes precede the values they override.
+    pub const ALL: [Control; 11] = [
+        Control::AutoExposure,
+        Control::Exposure,
+        Control::Gain,
+        Control::WhiteBalance,
+        Control::AutoFocus,
+        Control::Focus,
+        Control::Brightness,
+        Control::Contrast,
+        Control::Saturation,
+        Control::Sharpness,
+        Control::Zoom,
+    ];
+
+    /// Gets the OpenCV capture property of the control.
+    pub fn property(&self) -> i32 {
+        match self {
+            Control::AutoExposure => CAP_PROP_AUTO_EXPOSURE,
+            Control::Exposure => CAP_PROP_EXPOSURE,
+            Control::Gain => CAP_PROP_GAIN,
+            Control::WhiteBalance => CAP_PROP_WB_TEMPERATURE,
+            Control::AutoFocus => CAP_PROP_AUTOFOCUS,
+            Control::Focus => CAP_PROP_FOCUS,
+            Control::Brightness => CAP_PROP_BRIGHTNESS,
+            Control::Contrast => CAP_PROP_CONTRAST,
+            Control::Saturation => CAP_PROP_SATURATION,
+            Control::Sharpness => CAP_PROP_SHARPNESS,
+            Control::Zoom => CAP_PROP_ZOOM,
+        }
+    }
+
+    /// Whether the control is an on / off switch.
+    pub fn is_switch(&self) -> bool {
+        matches!(self, Control::AutoExposure | Control::AutoFocus)
+    }
+
+    /// Converts a control value to a property value of a specific capture backend.
+    /// Switches are 1.0 if on and 0.0 if off, backends encode automatic exposure differently.
+    pub fn to_property(&self, backend: i32, value: f64) -> f64 {
+        match self {
+            Control::AutoExposure => {
+                let (manual, auto) = auto_exposure_values(backend);
+                if value != 0.0 {
+                    auto
+                } else {
+                    manual
+                }
+            }
+            _ => value,
+        }
+    }
+
+    /// Converts a property value of a specific capture backend to a control value.
+    /// Returns `None` if the value does not represent a switch state,
+    /// e.g. -1.0 returned by backends that do not support the control.
+    pub fn from_property(&self, backend: i32, value: f64) -> Option<f64> {
+        let is = |expected: f64| (value - expected).abs() < 1e-3;
+        match self {
+            Control::AutoExposure => {
+                let (manual, auto) = auto_exposure_values(backend);
+                if is(manual) {
+                    Some(0.0)
+                } else if is(auto) || (backend == CAP_V4L2 && (is(0.0) || is(2.0))) {
+                    // V4L2 also reports full automatic and shutter priority modes
+                    Some(1.0)
+                } else {
+                    None
+                }
+            }
+            Control::AutoFocus => (is(0.0) || is(1.0)).then(|| value.round()),
+            _ => Some(value),
+        }
+    }
+}
+
+/// Gets the manual and automatic exposure property values of a capture backend.
+fn auto_exposure_values(backend: i32) -> (f64, f64) {
+    match backend {
+        CAP_DSHOW => (0.25, 0.75),
+        // V4L2_EXPOSURE_MANUAL and V4L2_EXPOSURE_APERTURE_PRIORITY
+        CAP_V4L2 => (1.0, 3.0),
+        _ => (0.0, 1.0),
+    }
+}
+
+impl Controls {
+    /// Validates that all control values are finite.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the first invalid setting.
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        for control in Control::ALL {
+            if self.get(control).is_some_and(|value| !value.is_finite()) {
+                return Err(format!("control {:?} value must be finite", control));
+            }
+        }
+        Ok(())
+    }
+
+    /// Gets a control value as a capture property value, switches are 1.0 if on and 0.0 if off.
+    pub fn get(&self, control: Control) -> Option<f64> {
+        let switch = |on: Option<bool>| on.map(|on| if on { 1.0 } else { 0.0 });
+        match control {
+            Control::AutoExposure => switch(self.auto_exposure),
+            Control::Exposure => self.exposure,
+            Control::Gain => self.gain,
+            Control::WhiteBalance => self.white_balance,
+            Control::AutoFocus => switch(self.auto_focus),
+            Control::Focus => self.focus,
+            Control::Brightness => self.brightness,
+            Control::Contrast => self.contrast,
+            Control::Saturation => self.saturation,
+            Control::Sharpness => self.sharpness,
+            Control::Zoom => self.zoom,
+        }
+    }
+
+    /// Sets a control value from a capture property value, switches are on if not 0.0.
+    pub fn set(&mut self, control: Control, value: Option<f64>) {
+        let switch = value.map(|value| value != 0.0);
+        match control {
+            Control::AutoExposure => self.auto_exposure = switch,
+            Control::Exposure => self.exposure = value,
+            Control::Gain => self.gain = value,
+            Control::WhiteBalance => self.white_balance = value,
+            Control::AutoFocus => self.auto_focus = switch,
+            Control::Focus => self.focus = value,
+            Control::Brightness => self.brightness = value,
+            Control::Contrast => self.contrast = value,
+            Control::Saturation => self.saturation = value,
+            Control::Sharpness => self.sharpness = value,
+            Control::Zoom => self.zoom = value,
+        }
+    }
+
+    /// Overrides control values with the ones set in other controls.
+    pub fn merge(&mut self, other: &Controls) {
+        for control in Control::ALL {
+            if let Some(value) = other.get(control) {
+                self.set(control, Some(value));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_controls() {
+        let mut controls = Controls {
+            auto_exposure: Some(true),
+            gain: Some(10.0),
+            ..Default::default()
+        };
+        controls.merge(&Controls {
+            auto_exposure: Some(false),
+            exposure: Some(-6.0),
+            ..Default::default()
+        });
+        assert_eq!(controls.get(Control::AutoExposure), Some(0.0));
+        assert_eq!(controls.exposure, Some(-6.0));
+        assert_eq!(controls.gain, Some(10.0));
+        assert!(controls.validate().is_ok());
+    }
+
+    #[test]
+    fn test_auto_exposure_property() {
+        for backend in [CAP_MSMF, CAP_DSHOW, CAP_V4L2] {
+            for value in [0.0, 1.0] {
+                let property = Control::AutoExposure.to_property(backend, value);
+                assert_eq!(
+                    Control::AutoExposure.from_property(backend, property),
+                    Some(value)
+                );
+            }
+        }
+        assert_eq!(Control::AutoExposure.to_property(CAP_DSHOW, 0.0), 0.25);
+        assert_eq!(Control::AutoExposure.from_property(CAP_MSMF, -1.0), None);
+        assert_eq!(Control::AutoFocus.from_property(CAP_MSMF, -1.0), None);
+    }
+}
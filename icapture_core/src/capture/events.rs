@@ -0,0 +1,222 @@
+//! Provides capture lifecycle events published to subscribers.
+
+use super::motion::MotionEvent;
+use super::quality::QualityMetrics;
+use super::watchdog::ReconnectEvent;
+use chrono::Local;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::{mpsc, Mutex};
+
+/// Defines capture event kinds.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum CaptureEventKind {
+    /// The capturing device has been opened
+    Initialized {
+        /// Capturing device index
+        device_id: u32,
+    },
+    /// The capturing device has been released
+    Disposed,
+    /// Video recording has started
+    RecordingStarted {
+        /// Path to the video file
+        file_path: PathBuf,
+    },
+    /// Video recording has stopped
+    RecordingStopped {
+        /// Path to the last video file
+        file_path: PathBuf,
+    },
+    /// Video recording has continued in a new file
+    SegmentRotated {
+        /// Path to the new video file
+        file_path: PathBuf,
+    },
+    /// A frame has been saved
+    FrameGrabbed {
+        /// Path to the image file
+        file_path: PathBuf,
+    },
+    /// Motion has started or stopped
+    Motion(MotionEvent),
+    /// The stream has been lost or the capturing device has been reconnected
+    Reconnect(ReconnectEvent),
+    /// Frame quality thresholds are violated, published when the violations change
+    QualityWarning(QualityMetrics),
+    /// An operation has been rejected since the capturing device is busy
+    Busy {
+        /// Rejected operation
+        operation: String,
+    },
+    /// Capturing in a background thread has failed
+    Error {
+        /// Error description
+        message: String,
+    },
+}
+
+/// Defines a capture event.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CaptureEvent {
+    /// Local time of the event in RFC 3339 format
+    pub timestamp: String,
+    /// Name of the capturing device
+    pub device_name: String,
+    /// Event kind and data
+    #[serde(flatten)]
+    pub kind: CaptureEventKind,
+}
+
+impl CaptureEventKind {
+    /// Gets the event name, as serialized in the `event` field.
+    pub fn name(&self) -> &'static str {
+        match self {
+            CaptureEventKind::Initialized { .. } => "initialized",
+            CaptureEventKind::Disposed => "disposed",
+            CaptureEventKind::RecordingStarted { .. } => "recording_started",
+            CaptureEventKind::RecordingStopped { .. } => "recording_stopped",
+            CaptureEventKind::SegmentRotated { .. } => "segment_rotated",
+            CaptureEventKind::FrameGrabbed { .. } => "frame_grabbed",
+            CaptureEventKind::Motion(_) => "motion",
+            CaptureEventKind::Reconnect(_) => "reconnect",
+            CaptureEventKind::QualityWarning(_) => "quality_warning",
+            CaptureEventKind::Busy { .. } => "busy",
+            CaptureEventKind::Error { .. } => "error",
+        }
+    }
+}
+
+/// Publishes capture events of a device to its subscribers.
+pub(crate) struct EventBus {
+    device_name: String,
+    subscribers: Mutex<Vec<mpsc::Sender<CaptureEvent>>>,
+}
+
+impl EventBus {
+    /// Constructor for an event bus without subscribers.
+    pub(crate) fn new(device_name: &str) -> Self {
+        EventBus {
+            device_name: device_name.to_string(),
+            subscribers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Subscribes to events, the subscription ends when the receiver is dropped.
+    pub(crate) fn subscribe(&self) -> mpsc::Receiver<CaptureEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.add_subscriber(sender);
+        receiver
+    }
+
+    /// Subscribes an existing channel to events, so that it can receive events of several devices.
+    pub(crate) fn add_subscriber(&self, sender: mpsc::Sender<CaptureEvent>) {
+        self.subscribers.lock().unwrap().push(sender);
+    }
+
+    /// Publishes an event to all subscribers.
+    pub(crate) fn publish(&self, kind: CaptureEventKind) {
+        let event = CaptureEvent {
+            timestamp: Local::now().to_rfc3339(),
+            device_name: self.device_name.clone(),
+            kind,
+        };
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::motion::MotionEventKind;
+    use crate::capture::quality::QualityIssue;
+    use crate::capture::watchdog::ReconnectEventKind;
+
+    #[test]
+    fn test_event_names() {
+        let file_path = PathBuf::from("test.mp4");
+        let kinds = [
+            CaptureEventKind::Initialized { device_id: 0 },
+            CaptureEventKind::Disposed,
+            CaptureEventKind::RecordingStarted {
+                file_path: file_path.clone(),
+            },
+            CaptureEventKind::RecordingStopped {
+                file_path: file_path.clone(),
+            },
+            CaptureEventKind::SegmentRotated {
+                file_path: file_path.clone(),
+            },
+            CaptureEventKind::FrameGrabbed { file_path },
+            CaptureEventKind::Motion(MotionEvent::new(MotionEventKind::Started, 0.5, None)),
+            CaptureEventKind::Reconnect(ReconnectEvent::new(
+                ReconnectEventKind::Disconnected,
+                "test",
+                0,
+                None,
+            )),
+            CaptureEventKind::QualityWarning(QualityMetrics {
+                timestamp: String::new(),
+                sharpness: 0.0,
+                brightness: 0.0,
+                dark_fraction: 0.0,
+                bright_fraction: 0.0,
+                frozen_frames: 0,
+                issues: vec![QualityIssue::TooDark],
+            }),
+            CaptureEventKind::Busy {
+                operation: "test".to_string(),
+            },
+            CaptureEventKind::Error {
+                message: "test".to_string(),
+            },
+        ];
+        for kind in kinds {
+            // fails to compile when a variant is added, so that it is added to the list above
+            match kind {
+                CaptureEventKind::Initialized { .. }
+                | CaptureEventKind::Disposed
+                | CaptureEventKind::RecordingStarted { .. }
+                | CaptureEventKind::RecordingStopped { .. }
+                | CaptureEventKind::SegmentRotated { .. }
+                | CaptureEventKind::FrameGrabbed { .. }
+                | CaptureEventKind::Motion(_)
+                | CaptureEventKind::Reconnect(_)
+                | CaptureEventKind::QualityWarning(_)
+                | CaptureEventKind::Busy { .. }
+                | CaptureEventKind::Error { .. } => {}
+            }
+            let json = serde_json::to_value(&kind).unwrap();
+            assert_eq!(json["event"], kind.name());
+        }
+    }
+
+    #[test]
+    fn test_publish_subscribe() {
+        let bus = EventBus::new("test device");
+        let receiver = bus.subscribe();
+        bus.publish(CaptureEventKind::RecordingStarted {
+            file_path: PathBuf::from("test.mp4"),
+        });
+        let event = receiver.try_recv().unwrap();
+        assert_eq!(event.device_name, "test device");
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["event"], event.kind.name());
+        assert_eq!(json["data"]["file_path"], "test.mp4");
+        assert_eq!(
+            event.kind,
+            CaptureEventKind::RecordingStarted {
+                file_path: PathBuf::from("test.mp4")
+            }
+        );
+        drop(receiver);
+        bus.publish(CaptureEventKind::Error {
+            message: "test error".to_string(),
+        });
+        assert!(bus.subscribers.lock().unwrap().is_empty());
+    }
+}
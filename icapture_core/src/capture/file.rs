@@ -1,21 +1,49 @@
 use chrono::Local;
 use log::debug;
+use std::ffi::OsStr;
 use std::fs;
 use std::io::Result;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-pub(crate) fn create_dir(dir_path: &str) -> Result<()> {
-    let path = Path::new(dir_path);
+/// Marks files being written, which are renamed to their final names when complete.
+pub(crate) const PARTIAL_MARKER: &str = "partial";
+
+pub(crate) fn create_dir(path: &Path) -> Result<()> {
     if !path.exists() {
         fs::create_dir_all(path)?;
-        debug!("directory created: {}", dir_path);
+        debug!("directory created: {}", path.display());
     } else {
-        debug!("directory already exists: {}", dir_path);
+        debug!("directory already exists: {}", path.display());
     }
     Ok(())
 }
 
-pub(crate) fn get_name(extension: &str) -> String {
-    let timestamp = Local::now().format("%Y-%m-%d_%H-%M-%S.%3f").to_string();
-    format!("{}.{}", timestamp, extension)
+/// Gets a path to write a file at until it is complete, `<name>.partial.<extension>`,
+/// the extension is kept since encoders are chosen by it.
+pub(crate) fn partial_path(path: &Path) -> PathBuf {
+    match path.extension() {
+        Some(extension) => path.with_extension(format!(
+            "{}.{}",
+            PARTIAL_MARKER,
+            extension.to_string_lossy()
+        )),
+        None => path.with_extension(PARTIAL_MARKER),
+    }
+}
+
+/// Renames a completely written file from its partial path to its final path.
+pub(crate) fn complete(path: &Path) -> Result<()> {
+    fs::rename(partial_path(path), path)?;
+    debug!("file completed: {}", path.display());
+    Ok(())
+}
+
+pub(crate) fn is_partial(path: &Path) -> bool {
+    let marker = Some(OsStr::new(PARTIAL_MARKER));
+    path.extension() == marker
+        || path.file_stem().map(Path::new).and_then(Path::extension) == marker
+}
+
+pub(crate) fn get_timestamp() -> String {
+    Local::now().format("%Y-%m-%d_%H-%M-%S.%3f").to_string()
 }
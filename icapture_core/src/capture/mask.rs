@@ -0,0 +1,230 @@
+//! Provides privacy masking of frame regions - blacking out or pixelating rectangles and polygons.
+
+use opencv::{
+    core::{self, Point, Rect, Scalar, Size, Vector},
+    imgproc,
+    prelude::*,
+    Result,
+};
+use serde::{Deserialize, Serialize};
+
+/// Defines a masked region in coordinates normalized to the frame size (0.0-1.0),
+/// so masks stay in place when the frame size changes.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum MaskShape {
+    /// Axis-aligned rectangle
+    Rectangle {
+        /// Left edge
+        x: f64,
+        /// Top edge
+        y: f64,
+        /// Rectangle width
+        width: f64,
+        /// Rectangle height
+        height: f64,
+    },
+    /// Polygon given by its vertices as `[x, y]` pairs
+    Polygon(Vec<[f64; 2]>),
+}
+
+/// Defines how a masked region is hidden.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum MaskFill {
+    /// Fill the region with black
+    #[default]
+    Black,
+    /// Pixelate the region with square blocks of a given size in pixels
+    Pixelate(u32),
+}
+
+/// Defines a privacy mask applied to every captured frame before it is saved or streamed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Mask {
+    /// Masked region
+    pub shape: MaskShape,
+    /// Fill of the masked region
+    #[serde(default)]
+    pub fill: MaskFill,
+}
+
+impl MaskShape {
+    /// Validates that the region is not empty and lies within the frame.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the first invalid setting.
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        let in_frame = |v: f64| (0.0..=1.0).contains(&v);
+        match self {
+            MaskShape::Rectangle {
+                x,
+                y,
+                width,
+                height,
+            } => {
+                if *width <= 0.0 || *height <= 0.0 {
+                    return Err("rectangle must not be empty".to_string());
+                }
+                if !in_frame(*x) || !in_frame(*y) || !in_frame(x + width) || !in_frame(y + height) {
+                    return Err(format!("{:?} is outside the frame", self));
+                }
+            }
+            MaskShape::Polygon(points) => {
+                if points.len() < 3 {
+                    return Err("polygon must have at least three points".to_string());
+                }
+                if !points.iter().all(|p| in_frame(p[0]) && in_frame(p[1])) {
+                    return Err(format!("{:?} is outside the frame", self));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Gets the region vertices in pixels for a specific frame size.
+    pub(crate) fn polygon(&self, size: Size) -> Vector<Point> {
+        let to_point = |x: f64, y: f64| {
+            Point::new(
+                (x * size.width as f64).round() as i32,
+                (y * size.height as f64).round() as i32,
+            )
+        };
+        match self {
+            MaskShape::Rectangle {
+                x,
+                y,
+                width,
+                height,
+            } => Vector::from_slice(&[
+                to_point(*x, *y),
+                to_point(x + width, *y),
+                to_point(x + width, y + height),
+                to_point(*x, y + height),
+            ]),
+            MaskShape::Polygon(points) => points.iter().map(|p| to_point(p[0], p[1])).collect(),
+        }
+    }
+}
+
+impl Mask {
+    /// Validates the mask settings.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the first invalid setting.
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        self.shape
+            .validate()
+            .map_err(|reason| format!("mask {}", reason))?;
+        if self.fill == MaskFill::Pixelate(0) {
+            return Err("mask pixel size must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    /// Hides the masked region of a frame.
+    pub(crate) fn apply(&self, frame: &mut Mat) -> Result<()> {
+        let size = frame.size()?;
+        let polygon = self.shape.polygon(size);
+        let rect = imgproc::bounding_rect(&polygon)? & Rect::new(0, 0, size.width, size.height);
+        if rect.width == 0 || rect.height == 0 {
+            return Ok(());
+        }
+
+        match (self.fill, &self.shape) {
+            (MaskFill::Black, MaskShape::Rectangle { .. }) => imgproc::rectangle(
+                frame,
+                rect,
+                Scalar::all(0.0),
+                imgproc::FILLED,
+                imgproc::LINE_8,
+                0,
+            ),
+            (MaskFill::Black, MaskShape::Polygon(_)) => {
+                let mut polygons = Vector::<Vector<Point>>::new();
+                polygons.push(polygon);
+                imgproc::fill_poly(
+                    frame,
+                    &polygons,
+                    Scalar::all(0.0),
+                    imgproc::LINE_8,
+                    0,
+                    Point::default(),
+                )
+            }
+            (MaskFill::Pixelate(block), MaskShape::Rectangle { .. }) => {
+                let pixelated = pixelate(&Mat::roi(&*frame, rect)?.try_clone()?, block)?;
+                pixelated.copy_to(&mut Mat::roi_mut(frame, rect)?)
+            }
+            (MaskFill::Pixelate(block), MaskShape::Polygon(_)) => {
+                let pixelated = pixelate(&Mat::roi(&*frame, rect)?.try_clone()?, block)?;
+                let mut region = Mat::new_rows_cols_with_default(
+                    rect.height,
+                    rect.width,
+                    core::CV_8UC1,
+                    Scalar::all(0.0),
+                )?;
+                let mut polygons = Vector::<Vector<Point>>::new();
+                polygons.push(polygon);
+                imgproc::fill_poly(
+                    &mut region,
+                    &polygons,
+                    Scalar::all(255.0),
+                    imgproc::LINE_8,
+                    0,
+                    Point::new(-rect.x, -rect.y),
+                )?;
+                pixelated.copy_to_masked(&mut Mat::roi_mut(frame, rect)?, &region)
+            }
+        }
+    }
+}
+
+fn pixelate(region: &Mat, block: u32) -> Result<Mat> {
+    let size = region.size()?;
+    let block = block as i32;
+    let small_size = Size::new((size.width / block).max(1), (size.height / block).max(1));
+    let mut small = Mat::default();
+    imgproc::resize(
+        region,
+        &mut small,
+        small_size,
+        0.0,
+        0.0,
+        imgproc::INTER_AREA,
+    )?;
+    let mut pixelated = Mat::default();
+    imgproc::resize(
+        &small,
+        &mut pixelated,
+        size,
+        0.0,
+        0.0,
+        imgproc::INTER_NEAREST,
+    )?;
+    Ok(pixelated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mask_outside_frame_is_rejected() {
+        let mask = Mask {
+            shape: MaskShape::Rectangle {
+                x: 0.5,
+                y: 0.5,
+                width: 0.6,
+                height: 0.2,
+            },
+            fill: MaskFill::Black,
+        };
+        assert!(mask.validate().is_err());
+        let mask = Mask {
+            shape: MaskShape::Polygon(vec![[0.1, 0.1], [0.9, 0.1], [0.5, 0.9]]),
+            fill: MaskFill::Pixelate(16),
+        };
+        assert!(mask.validate().is_ok());
+    }
+}
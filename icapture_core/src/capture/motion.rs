@@ -0,0 +1,226 @@
+//! Provides motion detection on the capturing stream using MOG2 background subtraction.
+
+use crate::capture::mask::MaskShape;
+use chrono::Local;
+use opencv::{
+    core::{self, Point, Ptr, Scalar, Size, Vector},
+    imgproc,
+    prelude::*,
+    video::{self, BackgroundSubtractorMOG2, BackgroundSubtractorMOG2Trait},
+    Result,
+};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+const DETECTION_WIDTH: i32 = 320;
+/// Maximum pre-roll in seconds, pre-roll frames are buffered in memory.
+const MAX_PRE_ROLL: f64 = 30.0;
+/// Maximum post-roll in seconds.
+const MAX_POST_ROLL: f64 = 600.0;
+
+/// Defines motion detection settings.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Motion {
+    /// Detection sensitivity (0.0-1.0), higher values react to smaller changes in pixel intensity
+    pub sensitivity: f64,
+    /// Minimum moving area as a fraction of the watched zones (0.0-1.0) to report motion
+    pub min_area: f64,
+    /// Watched zones in normalized frame coordinates, the whole frame if empty
+    pub zones: Vec<MaskShape>,
+    /// Number of frames the background model is built from
+    pub history: u32,
+    /// Record video while motion is detected
+    pub record: bool,
+    /// Seconds of video recorded before motion is detected
+    pub pre_roll: f64,
+    /// Seconds of video recorded after motion is no longer detected
+    pub post_roll: f64,
+}
+
+/// Defines motion event kinds.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub enum MotionEventKind {
+    /// Motion has been detected
+    Started,
+    /// Motion has not been detected for the post-roll period
+    Stopped,
+}
+
+/// Defines a motion event.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct MotionEvent {
+    /// Local time of the event in RFC 3339 format
+    pub timestamp: String,
+    /// Event kind
+    pub kind: MotionEventKind,
+    /// Moving area as a fraction of the watched zones
+    pub area: f64,
+    /// Path to the video file recorded for the motion, if any
+    pub file_path: Option<PathBuf>,
+}
+
+pub(crate) struct MotionDetector {
+    subtractor: Ptr<BackgroundSubtractorMOG2>,
+    zones: Vec<MaskShape>,
+    zone_mask: Mat,
+    zone_area: i32,
+}
+
+impl Default for Motion {
+    fn default() -> Self {
+        Motion {
+            sensitivity: 0.8,
+            min_area: 0.01,
+            zones: Vec::new(),
+            history: 500,
+            record: true,
+            pre_roll: 2.0,
+            post_roll: 5.0,
+        }
+    }
+}
+
+impl Motion {
+    /// Validates the motion detection settings.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the first invalid setting.
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        if !(0.0..=1.0).contains(&self.sensitivity) {
+            return Err(format!(
+                "motion sensitivity {} is out of range 0-1",
+                self.sensitivity
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.min_area) {
+            return Err(format!(
+                "motion minimum area {} is out of range 0-1",
+                self.min_area
+            ));
+        }
+        if self.history == 0 {
+            return Err("motion history must be positive".to_string());
+        }
+        if !(0.0..=MAX_PRE_ROLL).contains(&self.pre_roll) {
+            return Err(format!(
+                "motion pre-roll {} is out of range 0-{}",
+                self.pre_roll, MAX_PRE_ROLL
+            ));
+        }
+        if !(0.0..=MAX_POST_ROLL).contains(&self.post_roll) {
+            return Err(format!(
+                "motion post-roll {} is out of range 0-{}",
+                self.post_roll, MAX_POST_ROLL
+            ));
+        }
+        for zone in &self.zones {
+            zone.validate()
+                .map_err(|reason| format!("motion zone {}", reason))?;
+        }
+        Ok(())
+    }
+}
+
+impl MotionEvent {
+    pub(crate) fn new(kind: MotionEventKind, area: f64, file_path: Option<PathBuf>) -> Self {
+        MotionEvent {
+            timestamp: Local::now().to_rfc3339(),
+            kind,
+            area,
+            file_path,
+        }
+    }
+}
+
+impl MotionDetector {
+    pub(crate) fn new(config: &Motion) -> Result<Self> {
+        let var_threshold = 4.0 + (1.0 - config.sensitivity) * 60.0;
+        let subtractor =
+            video::create_background_subtractor_mog2(config.history as i32, var_threshold, true)?;
+        Ok(MotionDetector {
+            subtractor,
+            zones: config.zones.clone(),
+            zone_mask: Mat::default(),
+            zone_area: 0,
+        })
+    }
+
+    /// Gets the moving area of a frame as a fraction of the watched zones.
+    pub(crate) fn detect(&mut self, frame: &Mat) -> Result<f64> {
+        let size = frame.size()?;
+        let size = Size::new(
+            DETECTION_WIDTH.min(size.width),
+            size.height * DETECTION_WIDTH.min(size.width) / size.width.max(1),
+        );
+        let mut small = Mat::default();
+        imgproc::resize(frame, &mut small, size, 0.0, 0.0, imgproc::INTER_AREA)?;
+
+        let mut foreground = Mat::default();
+        BackgroundSubtractorMOG2Trait::apply(&mut self.subtractor, &small, &mut foreground, -1.0)?;
+        // shadows are marked as 127 in the foreground mask, only definite motion is counted
+        let mut moving = Mat::default();
+        imgproc::threshold(
+            &foreground,
+            &mut moving,
+            200.0,
+            255.0,
+            imgproc::THRESH_BINARY,
+        )?;
+
+        if self.zones.is_empty() {
+            let area = (size.width * size.height).max(1);
+            return Ok(core::count_non_zero(&moving)? as f64 / area as f64);
+        }
+        if self.zone_mask.size()? != size {
+            self.zone_mask = Mat::new_rows_cols_with_default(
+                size.height,
+                size.width,
+                core::CV_8UC1,
+                Scalar::all(0.0),
+            )?;
+            let polygons: Vector<Vector<Point>> =
+                self.zones.iter().map(|zone| zone.polygon(size)).collect();
+            imgproc::fill_poly(
+                &mut self.zone_mask,
+                &polygons,
+                Scalar::all(255.0),
+                imgproc::LINE_8,
+                0,
+                Point::default(),
+            )?;
+            self.zone_area = core::count_non_zero(&self.zone_mask)?;
+        }
+        let mut zone_moving = Mat::default();
+        core::bitwise_and(
+            &moving,
+            &self.zone_mask,
+            &mut zone_moving,
+            &core::no_array(),
+        )?;
+        Ok(core::count_non_zero(&zone_moving)? as f64 / self.zone_area.max(1) as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_roll() {
+        assert!(Motion::default().validate().is_ok());
+        for pre_roll in [-1.0, f64::NAN, f64::INFINITY, MAX_PRE_ROLL + 1.0] {
+            let motion = Motion {
+                pre_roll,
+                ..Default::default()
+            };
+            assert!(motion.validate().is_err(), "{}", pre_roll);
+        }
+        let motion = Motion {
+            post_roll: f64::INFINITY,
+            ..Default::default()
+        };
+        assert!(motion.validate().is_err());
+    }
+}
@@ -0,0 +1,400 @@
+//! Provides synchronized capturing from several devices.
+
+use super::{
+    color::ColorMode,
+    composite::Composite,
+    events::{CaptureEvent, CaptureEventKind},
+    file,
+    naming::{FileNamer, Naming},
+    overlay::OverlayContext,
+    Capture, CaptureArtifact, CaptureError, FrameSkew,
+};
+use crate::config::Config;
+use chrono::Local;
+use log::{debug, error};
+use opencv::prelude::*;
+use serde::Serialize;
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::Ordering,
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// Delay between starting the recording and its first frame, so that all devices are ready.
+const START_DELAY_MS: u64 = 500;
+
+/// Defines a synchronized video recording from several devices.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct MultiVideoOutput {
+    /// Session prefix shared by the file names
+    pub session: String,
+    /// Local time of the shared start in RFC 3339 format
+    pub start_time: String,
+    /// Video files being recorded, in the order of devices
+    pub outputs: Vec<CaptureArtifact>,
+}
+
+/// Defines a group of video capturing objects recording in sync.
+pub struct MultiCapture {
+    captures: Vec<Capture>,
+}
+
+impl MultiCapture {
+    /// Constructor for a group of video capturing objects, one per configuration.
+    /// Devices opened before a failure are disposed.
+    pub fn new(configs: &[Config]) -> Result<Self, CaptureError> {
+        Self::create(configs, None)
+    }
+
+    /// Constructor for a group of video capturing objects with an event subscriber
+    /// receiving events of all devices, including their initialization events.
+    pub fn new_with_events(
+        configs: &[Config],
+        events: Sender<CaptureEvent>,
+    ) -> Result<Self, CaptureError> {
+        Self::create(configs, Some(events))
+    }
+
+    fn create(
+        configs: &[Config],
+        events: Option<Sender<CaptureEvent>>,
+    ) -> Result<Self, CaptureError> {
+        debug!("create multi capture instance");
+        if configs.is_empty() {
+            let err = CaptureError::InvalidConfig("no capture device configured".to_string());
+            error!("{}", err);
+            return Err(err);
+        }
+        let mut device_ids = HashSet::new();
+        if let Some(config) = configs.iter().find(|c| !device_ids.insert(c.device_id)) {
+            let err = CaptureError::InvalidConfig(format!(
+                "device {} is configured more than once",
+                config.device_id
+            ));
+            error!("{}", err);
+            return Err(err);
+        }
+
+        let mut captures: Vec<Capture> = Vec::with_capacity(configs.len());
+        for config in configs {
+            let capture = match &events {
+                Some(events) => Capture::new_with_events(config, events.clone()),
+                None => Capture::new(config),
+            };
+            match capture {
+                Ok(capture) => captures.push(capture),
+                Err(err) => {
+                    for capture in captures.iter_mut() {
+                        let _ = capture.dispose();
+                    }
+                    return Err(err);
+                }
+            }
+        }
+        Ok(MultiCapture { captures })
+    }
+
+    /// Destructor for a group of video capturing objects.
+    /// All devices are released, the first error is returned.
+    pub fn dispose(&mut self) -> Result<(), CaptureError> {
+        debug!("dispose multi capture instance");
+        let mut result = Ok(());
+        for capture in self.captures.iter_mut() {
+            let disposed = capture.dispose();
+            if result.is_ok() {
+                result = disposed;
+            }
+        }
+        result
+    }
+
+    /// Gets the video capturing objects, in the order of configurations.
+    pub fn captures(&self) -> &[Capture] {
+        &self.captures
+    }
+
+    /// Gets the mutable video capturing objects, in the order of configurations.
+    pub fn captures_mut(&mut self) -> &mut [Capture] {
+        &mut self.captures
+    }
+
+    /// Saves a frame from each device with the default file name.
+    pub fn grab_frame(&mut self) -> Result<Vec<CaptureArtifact>, CaptureError> {
+        self.grab_frame_named(&Naming::default())
+    }
+
+    /// Saves a frame from each device named by a given naming,
+    /// which takes precedence over the configured naming of each device.
+    pub fn grab_frame_named(
+        &mut self,
+        naming: &Naming,
+    ) -> Result<Vec<CaptureArtifact>, CaptureError> {
+        self.captures
+            .iter_mut()
+            .map(|capture| capture.grab_frame_named(naming))
+            .collect()
+    }
+
+    /// Starts capturing video streams of all devices with a shared start time.
+    /// File names share the session prefix and end with the device index,
+    /// `<session>_<index>.<container_extension>`, in the data directory of each device.
+    /// If any device fails to start, the devices started before are stopped.
+    pub fn start_grab_video(&mut self) -> Result<MultiVideoOutput, CaptureError> {
+        self.start_grab_video_named(&Naming::default())
+    }
+
+    /// Starts capturing video streams of all devices with a shared start time,
+    /// named by a given naming, which takes precedence over the configured naming of each device.
+    /// Devices without a file name template keep the session prefix file names.
+    pub fn start_grab_video_named(
+        &mut self,
+        naming: &Naming,
+    ) -> Result<MultiVideoOutput, CaptureError> {
+        let session = file::get_timestamp();
+        debug!("start multi capture session {}", session);
+        let start_time = Instant::now() + Duration::from_millis(START_DELAY_MS);
+        let start_timestamp = Local::now() + chrono::Duration::milliseconds(START_DELAY_MS as i64);
+
+        let mut outputs = Vec::with_capacity(self.captures.len());
+        for index in 0..self.captures.len() {
+            let capture = &self.captures[index];
+            let data_dir = &capture.config.data_dir;
+            let extension = capture.config.video_container().file_extension();
+            let file_path = capture
+                .naming(naming)
+                .and_then(|naming| match naming.template {
+                    Some(_) => capture.namer.file_path(data_dir, &naming, extension),
+                    None => {
+                        let name = format!("{}_{}", session, index);
+                        FileNamer::join(data_dir, &naming, &name, extension)
+                    }
+                });
+            let started = file_path.and_then(|file_path| {
+                self.captures[index].start_grab_video_to_file_at(&file_path, start_time)
+            });
+            match started {
+                Ok(output) => outputs.push(output),
+                Err(err) => {
+                    for capture in self.captures[..index].iter_mut() {
+                        let _ = capture.stop_grab_video();
+                    }
+                    return Err(err);
+                }
+            }
+        }
+        Ok(MultiVideoOutput {
+            session,
+            start_time: start_timestamp.to_rfc3339(),
+            outputs,
+        })
+    }
+
+    /// Starts capturing video streams of all devices composed into a grid in one file,
+    /// `<session>_grid.<container_extension>`, encoded with the codec settings
+    /// and saved in the data directory of the first device, in its naming subdirectory if set.
+    /// Each device is read continuously and the grid is sampled for the latest frames
+    /// at the composite frame rate. Stopping any of the devices stops the composite recording.
+    pub fn start_grab_composite(
+        &mut self,
+        composite: &Composite,
+    ) -> Result<CaptureArtifact, CaptureError> {
+        if let Err(reason) = composite.validate() {
+            let err = CaptureError::InvalidConfig(reason);
+            error!("{}", err);
+            return Err(err);
+        }
+        let session = file::get_timestamp();
+        debug!("start composite capture session {}", session);
+        let fps = match composite.fps {
+            Some(fps) => fps,
+            None => self.captures[0].get_fps()?,
+        };
+
+        if self
+            .captures
+            .iter()
+            .any(|capture| capture.is_grabbing.load(Ordering::Relaxed))
+        {
+            return Err(self.captures[0].busy_error("grab composite"));
+        }
+
+        let mut writer_config = self.captures[0].config.clone();
+        writer_config.color_mode = ColorMode::BGR;
+        let naming = self.captures[0].naming(&Naming::default())?;
+        let file_path = FileNamer::join(
+            &writer_config.data_dir,
+            &naming,
+            &format!("{}_grid", session),
+            writer_config.video_container().file_extension(),
+        )?;
+        let grid_size = composite.frame_size(self.captures.len());
+        let (mut writer, output) =
+            match Capture::open_writer(&writer_config, &file_path, fps as f64, grid_size) {
+                Ok(result) => result,
+                Err(err) => {
+                    error!("{}", err);
+                    return Err(err);
+                }
+            };
+
+        let mut slots = Vec::with_capacity(self.captures.len());
+        let mut flags = Vec::with_capacity(self.captures.len());
+        let mut readers = Vec::with_capacity(self.captures.len());
+        for capture in self.captures.iter() {
+            let slot = Arc::new(Mutex::new(None));
+            capture.is_grabbing.store(true, Ordering::Relaxed);
+            readers.push(Self::spawn_reader(capture, Arc::clone(&slot)));
+            slots.push(slot);
+            flags.push(Arc::clone(&capture.is_grabbing));
+        }
+        let labels: Vec<String> = self
+            .captures
+            .iter()
+            .map(|capture| capture.device_name.clone())
+            .collect();
+        let composite = composite.clone();
+        let event_bus = Arc::clone(&self.captures[0].event_bus);
+        let frame_size = (grid_size.width as u32, grid_size.height as u32);
+        let timestamp = Local::now();
+        let artifact = CaptureArtifact::video(&output, timestamp, frame_size, 0, fps);
+        event_bus.publish(CaptureEventKind::RecordingStarted {
+            file_path: output.file_path.clone(),
+        });
+
+        // the first device waits for the grid file when stopped
+        self.captures[0].recorder = Some(thread::spawn(move || {
+            debug!("spawn composite thread");
+
+            let start_time = Instant::now();
+            let mut written_frames: u64 = 0;
+            let mut frame_count: u64 = 0;
+
+            while flags.iter().all(|flag| flag.load(Ordering::Relaxed)) {
+                let elapsed = start_time.elapsed();
+                let target_frame_count = (elapsed.as_secs_f64() * fps as f64).floor() as u64;
+
+                if frame_count < target_frame_count {
+                    let frames: Vec<Option<Mat>> = slots
+                        .iter()
+                        .map(|slot| {
+                            slot.lock()
+                                .unwrap()
+                                .as_ref()
+                                .and_then(|f| f.try_clone().ok())
+                        })
+                        .collect();
+                    match composite.compose(&frames, &labels) {
+                        Ok(grid) => match writer.write(&grid) {
+                            Ok(()) => written_frames += 1,
+                            Err(err) => Capture::report_error(&event_bus, &err),
+                        },
+                        Err(err) => Capture::report_error(&event_bus, &err),
+                    }
+                    frame_count += 1;
+                } else {
+                    thread::sleep(Duration::from_millis(1000_u64 / (2 * fps as u64)));
+                }
+            }
+
+            for flag in flags.iter() {
+                flag.store(false, Ordering::Relaxed);
+            }
+            // readers left running would take frames from the next recording
+            for reader in readers {
+                let _ = reader.join();
+            }
+            Capture::finish_video(writer, &output, &event_bus);
+            event_bus.publish(CaptureEventKind::RecordingStopped {
+                file_path: output.file_path.clone(),
+            });
+            vec![CaptureArtifact::video(
+                &output,
+                timestamp,
+                frame_size,
+                written_frames,
+                fps,
+            )]
+        }));
+
+        Ok(artifact)
+    }
+
+    /// Stops capturing video streams of all devices.
+    /// Waits for the video files to be finalized and returns them.
+    pub fn stop_grab_video(&mut self) -> Result<Vec<CaptureArtifact>, CaptureError> {
+        let mut result = Ok(Vec::new());
+        for capture in self.captures.iter_mut() {
+            match (capture.stop_grab_video(), &mut result) {
+                (Ok(artifact), Ok(artifacts)) => artifacts.extend(artifact),
+                (Err(err), Ok(_)) => result = Err(err),
+                _ => {}
+            }
+        }
+        result
+    }
+
+    fn spawn_reader(capture: &Capture, slot: Arc<Mutex<Option<Mat>>>) -> JoinHandle<()> {
+        let device = Arc::clone(&capture.capture);
+        let config = capture.config.clone();
+        let color_mode = config.color_mode.for_encoding();
+        let device_name = capture.device_name.clone();
+        let frame_number = Arc::clone(&capture.frame_number);
+        let live_frame = Arc::clone(&capture.live_frame);
+        let event_bus = Arc::clone(&capture.event_bus);
+        let is_grabbing = Arc::clone(&capture.is_grabbing);
+
+        thread::spawn(move || {
+            debug!("spawn composite reader thread for {}", device_name);
+            while is_grabbing.load(Ordering::Relaxed) {
+                let mut frame = Mat::default();
+                if !device.lock().unwrap().read(&mut frame).unwrap_or(false) || frame.empty() {
+                    thread::sleep(Duration::from_millis(10));
+                    continue;
+                }
+                let context = OverlayContext {
+                    device_name: &device_name,
+                    frame_number: frame_number.fetch_add(1, Ordering::Relaxed) + 1,
+                };
+                let overlay = config.overlay.enabled.then_some(&context);
+                match Capture::process_frame(&config, frame, color_mode, overlay) {
+                    Ok(frame) => {
+                        live_frame
+                            .lock()
+                            .unwrap()
+                            .publish(&frame, config.stream.frame_interval());
+                        *slot.lock().unwrap() = Some(frame);
+                    }
+                    Err(err) => Capture::report_error(&event_bus, &err),
+                }
+            }
+        })
+    }
+
+    /// Sets the session name used by the `{session}` placeholder of file name templates.
+    pub fn set_session(&mut self, session: &str) {
+        for capture in self.captures.iter_mut() {
+            capture.set_session(session);
+        }
+    }
+
+    /// Subscribes to capture events of all devices,
+    /// the subscription ends when the receiver is dropped.
+    pub fn subscribe(&self) -> Receiver<CaptureEvent> {
+        let (sender, receiver) = mpsc::channel();
+        for capture in self.captures.iter() {
+            capture.event_bus.add_subscriber(sender.clone());
+        }
+        receiver
+    }
+
+    /// Gets frame timing skew of each device, comparing the values across devices
+    /// shows how far apart the devices sample the shared timeline.
+    pub fn frame_skew(&self) -> Vec<FrameSkew> {
+        self.captures.iter().map(Capture::frame_skew).collect()
+    }
+}
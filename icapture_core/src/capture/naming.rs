@@ -0,0 +1,236 @@
+//! Provides naming of captured files from templates.
+
+use super::{file, CaptureError};
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+/// Placeholders supported in file name templates.
+const PLACEHOLDERS: [&str; 4] = ["{timestamp}", "{device}", "{seq}", "{session}"];
+/// Template used if no template is set.
+const DEFAULT_TEMPLATE: &str = "{timestamp}";
+
+/// Defines naming of captured files, unset values fall back to the defaults.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Naming {
+    /// File name template without the extension, `{timestamp}` if not set.
+    /// Supports the `{timestamp}`, `{device}`, `{seq}` and `{session}` placeholders.
+    /// Templates should include `{timestamp}`, since `{seq}` restarts with each device,
+    /// a numeric suffix is added to names of existing files.
+    pub template: Option<String>,
+    /// Subdirectory of the data directory to store files in
+    pub subdir: Option<String>,
+}
+
+/// Renders file names of a capturing device, counting the files named.
+#[derive(Clone, Debug)]
+pub(crate) struct FileNamer {
+    device: String,
+    session: String,
+    seq: Arc<AtomicU64>,
+}
+
+impl Naming {
+    /// Validates the naming settings.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the first invalid setting.
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        if let Some(template) = &self.template {
+            let stripped = PLACEHOLDERS
+                .iter()
+                .fold(template.clone(), |name, placeholder| {
+                    name.replace(placeholder, "")
+                });
+            if template.trim().is_empty() || template.trim_matches('.').is_empty() {
+                return Err("file name template must not be empty".to_string());
+            }
+            if stripped.contains(['{', '}']) {
+                return Err(format!(
+                    "unknown placeholder in file name template '{}'",
+                    template
+                ));
+            }
+            if stripped.contains(['/', '\\', ':']) {
+                return Err(format!(
+                    "file name template '{}' must not contain a path",
+                    template
+                ));
+            }
+            // names with the marker would be taken for files being written
+            if template.split('.').any(|part| part == file::PARTIAL_MARKER) {
+                return Err(format!(
+                    "file name template '{}' must not contain '.{}'",
+                    template,
+                    file::PARTIAL_MARKER
+                ));
+            }
+        }
+        if let Some(subdir) = &self.subdir {
+            let path = Path::new(subdir);
+            let is_relative = path
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)));
+            if subdir.is_empty() || !is_relative || subdir.contains(':') {
+                return Err(format!(
+                    "subdirectory '{}' must be relative to the data directory",
+                    subdir
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Merges naming settings, the values set in `other` take precedence.
+    pub fn merge(&mut self, other: &Naming) {
+        if other.template.is_some() {
+            self.template = other.template.clone();
+        }
+        if other.subdir.is_some() {
+            self.subdir = other.subdir.clone();
+        }
+    }
+}
+
+impl FileNamer {
+    pub(crate) fn new(device: &str, session: &str) -> Self {
+        FileNamer {
+            device: device.to_string(),
+            session: session.to_string(),
+            seq: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub(crate) fn set_session(&mut self, session: &str) {
+        self.session = session.to_string();
+    }
+
+    /// Renders a file name without the extension.
+    pub(crate) fn render(&self, template: Option<&str>) -> String {
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed) + 1;
+        template
+            .unwrap_or(DEFAULT_TEMPLATE)
+            .replace("{timestamp}", &file::get_timestamp())
+            .replace("{device}", &sanitize(&self.device))
+            .replace("{seq}", &seq.to_string())
+            .replace("{session}", &sanitize(&self.session))
+    }
+
+    /// Gets a path of a new file in the data directory,
+    /// creating the subdirectory if it does not exist.
+    pub(crate) fn file_path(
+        &self,
+        data_dir: &Path,
+        naming: &Naming,
+        extension: &str,
+    ) -> Result<PathBuf, CaptureError> {
+        let name = self.render(naming.template.as_deref());
+        Self::join(data_dir, naming, &name, extension)
+    }
+
+    /// Gets a path of a file with a given name in the data directory,
+    /// creating the subdirectory if it does not exist. Existing files are never overwritten,
+    /// a numeric suffix is added to the name instead, e.g. `frame_1.png`.
+    pub(crate) fn join(
+        data_dir: &Path,
+        naming: &Naming,
+        name: &str,
+        extension: &str,
+    ) -> Result<PathBuf, CaptureError> {
+        let mut dir = data_dir.to_path_buf();
+        if let Some(subdir) = &naming.subdir {
+            dir.push(subdir);
+            if file::create_dir(&dir).is_err() {
+                let err = CaptureError::CreateFileDirectory(dir.display().to_string());
+                error!("{}", err);
+                return Err(err);
+            }
+        }
+        let mut path = dir.join(format!("{}.{}", name, extension));
+        let mut suffix = 0;
+        while path.exists() || file::partial_path(&path).exists() {
+            suffix += 1;
+            path = dir.join(format!("{}_{}.{}", name, suffix, extension));
+        }
+        Ok(path)
+    }
+}
+
+/// Replaces characters which are not safe in file names.
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let namer = FileNamer::new("USB Capture 4K+", "bench");
+        assert_eq!(
+            namer.render(Some("{session}_{device}_{seq}")),
+            "bench_USB_Capture_4K__1"
+        );
+        assert_eq!(namer.render(Some("frame_{seq}")), "frame_2");
+
+        let valid = Naming {
+            template: Some("{device}_{timestamp}".to_string()),
+            subdir: Some("day/1".to_string()),
+        };
+        assert!(valid.validate().is_ok());
+        for template in [
+            "",
+            "..",
+            "{index}",
+            "../{seq}",
+            "c:{seq}",
+            "{device}.partial",
+        ] {
+            let naming = Naming {
+                template: Some(template.to_string()),
+                subdir: None,
+            };
+            assert!(naming.validate().is_err());
+        }
+        for subdir in ["", "..", "day/../..", "/tmp", "c:\\tmp"] {
+            let naming = Naming {
+                template: None,
+                subdir: Some(subdir.to_string()),
+            };
+            assert!(naming.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn test_join_existing() {
+        let dir = std::env::temp_dir().join("icapture_naming_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let naming = Naming::default();
+        let path = FileNamer::join(&dir, &naming, "frame", "png").unwrap();
+        assert_eq!(path, dir.join("frame.png"));
+        std::fs::write(&path, b"png").unwrap();
+        std::fs::write(dir.join("frame_1.partial.png"), b"png").unwrap();
+        let path = FileNamer::join(&dir, &naming, "frame", "png").unwrap();
+        assert_eq!(path, dir.join("frame_2.png"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
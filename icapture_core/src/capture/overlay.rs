@@ -0,0 +1,178 @@
+//! Provides text overlays burned into captured frames - timestamp, device name, frame number and label.
+
+use chrono::{
+    format::{Item, StrftimeItems},
+    Local,
+};
+use opencv::{
+    core::{Point, Rect, Scalar},
+    imgproc,
+    prelude::*,
+    Result,
+};
+use serde::{Deserialize, Serialize};
+
+const FONT_FACE: i32 = imgproc::FONT_HERSHEY_SIMPLEX;
+const MARGIN: i32 = 8;
+
+/// Defines overlay positions within a frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Position {
+    /// Top left corner
+    #[default]
+    TopLeft,
+    /// Top right corner
+    TopRight,
+    /// Bottom left corner
+    BottomLeft,
+    /// Bottom right corner
+    BottomRight,
+}
+
+/// Defines a text overlay, each enabled item is drawn on a separate line.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Overlay {
+    /// Draw the overlay on saved frames and video
+    pub enabled: bool,
+    /// Draw the overlay on previewed frames
+    pub preview: bool,
+    /// Overlay position
+    pub position: Position,
+    /// Font scale factor
+    pub font_scale: f64,
+    /// Text color as RGB
+    pub color: [u8; 3],
+    /// Background box color as RGB, no background box if not set
+    pub background: Option<[u8; 3]>,
+    /// Wall-clock time format in `strftime` syntax, no timestamp if not set
+    pub timestamp_format: Option<String>,
+    /// Draw the capturing device name
+    pub device_name: bool,
+    /// Draw the frame number
+    pub frame_number: bool,
+    /// Custom label, no label if not set
+    pub label: Option<String>,
+}
+
+/// Defines per-frame values drawn by an overlay.
+pub(crate) struct OverlayContext<'a> {
+    /// Capturing device name
+    pub(crate) device_name: &'a str,
+    /// Number of the frame read from the device
+    pub(crate) frame_number: u64,
+}
+
+impl Default for Overlay {
+    fn default() -> Self {
+        Overlay {
+            enabled: false,
+            preview: false,
+            position: Position::TopLeft,
+            font_scale: 1.0,
+            color: [255, 255, 255],
+            background: Some([0, 0, 0]),
+            timestamp_format: Some(String::from("%Y-%m-%d %H:%M:%S%.3f")),
+            device_name: false,
+            frame_number: false,
+            label: None,
+        }
+    }
+}
+
+impl Overlay {
+    /// Validates the overlay settings.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the first invalid setting.
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        if !(self.font_scale > 0.0 && self.font_scale.is_finite()) {
+            return Err(format!("font scale {} must be positive", self.font_scale));
+        }
+        if let Some(format) = &self.timestamp_format {
+            if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+                return Err(format!("invalid timestamp format '{}'", format));
+            }
+        }
+        Ok(())
+    }
+
+    /// Draws the overlay on a frame.
+    pub(crate) fn apply(&self, frame: &mut Mat, context: &OverlayContext) -> Result<()> {
+        let lines = self.lines(context);
+        if lines.is_empty() {
+            return Ok(());
+        }
+
+        let thickness = (self.font_scale * 2.0).round().max(1.0) as i32;
+        let mut sizes = Vec::with_capacity(lines.len());
+        for line in &lines {
+            let mut baseline = 0;
+            let size =
+                imgproc::get_text_size(line, FONT_FACE, self.font_scale, thickness, &mut baseline)?;
+            sizes.push((size.width, size.height + baseline));
+        }
+        let line_height = sizes.iter().map(|s| s.1).max().unwrap_or(0) + MARGIN / 2;
+        let box_width = sizes.iter().map(|s| s.0).max().unwrap_or(0) + MARGIN * 2;
+        let box_height = line_height * lines.len() as i32 + MARGIN;
+
+        let frame_size = frame.size()?;
+        let x = match self.position {
+            Position::TopLeft | Position::BottomLeft => 0,
+            Position::TopRight | Position::BottomRight => (frame_size.width - box_width).max(0),
+        };
+        let y = match self.position {
+            Position::TopLeft | Position::TopRight => 0,
+            Position::BottomLeft | Position::BottomRight => (frame_size.height - box_height).max(0),
+        };
+
+        if let Some(background) = self.background {
+            let rect = Rect::new(x, y, box_width, box_height);
+            imgproc::rectangle(
+                frame,
+                rect,
+                to_scalar(background),
+                imgproc::FILLED,
+                imgproc::LINE_8,
+                0,
+            )?;
+        }
+        for (i, line) in lines.iter().enumerate() {
+            let origin = Point::new(x + MARGIN, y + line_height * (i as i32 + 1));
+            imgproc::put_text(
+                frame,
+                line,
+                origin,
+                FONT_FACE,
+                self.font_scale,
+                to_scalar(self.color),
+                thickness,
+                imgproc::LINE_AA,
+                false,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn lines(&self, context: &OverlayContext) -> Vec<String> {
+        let mut lines = Vec::new();
+        if let Some(format) = &self.timestamp_format {
+            lines.push(Local::now().format(format).to_string());
+        }
+        if self.device_name {
+            lines.push(context.device_name.to_string());
+        }
+        if self.frame_number {
+            lines.push(format!("frame {}", context.frame_number));
+        }
+        if let Some(label) = &self.label {
+            lines.push(label.clone());
+        }
+        lines
+    }
+}
+
+fn to_scalar(rgb: [u8; 3]) -> Scalar {
+    Scalar::new(rgb[2] as f64, rgb[1] as f64, rgb[0] as f64, 0.0)
+}
@@ -0,0 +1,266 @@
+//! Provides image quality metrics of captured frames - focus, exposure and frozen stream detection.
+
+use chrono::Local;
+use opencv::{
+    core::{self, Scalar, Size},
+    imgproc,
+    prelude::*,
+    Result,
+};
+use serde::{Deserialize, Serialize};
+
+const ANALYSIS_WIDTH: i32 = 320;
+const DARK_LEVEL: f64 = 16.0;
+const BRIGHT_LEVEL: f64 = 239.0;
+
+/// Defines quality analysis settings, checks are skipped for thresholds that are not set.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Quality {
+    /// Analyze quality of captured frames, disabled by default since every frame is analyzed
+    pub enabled: bool,
+    /// Minimum sharpness as variance of the Laplacian
+    pub min_sharpness: Option<f64>,
+    /// Minimum mean brightness (0-255)
+    pub min_brightness: Option<f64>,
+    /// Maximum mean brightness (0-255)
+    pub max_brightness: Option<f64>,
+    /// Maximum number of consecutive identical frames
+    pub max_frozen_frames: Option<u32>,
+    /// Fail grabbing a frame that violates the thresholds
+    pub fail_grab: bool,
+}
+
+/// Defines quality issues of a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub enum QualityIssue {
+    /// Sharpness is below the threshold
+    Blurry,
+    /// Mean brightness is below the threshold
+    TooDark,
+    /// Mean brightness is above the threshold
+    TooBright,
+    /// Too many consecutive identical frames
+    Frozen,
+}
+
+/// Defines quality metrics of a frame.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct QualityMetrics {
+    /// Local time of the analysis in RFC 3339 format
+    pub timestamp: String,
+    /// Sharpness as variance of the Laplacian
+    pub sharpness: f64,
+    /// Mean brightness (0-255)
+    pub brightness: f64,
+    /// Fraction of nearly black pixels
+    pub dark_fraction: f64,
+    /// Fraction of nearly white pixels
+    pub bright_fraction: f64,
+    /// Number of consecutive identical frames
+    pub frozen_frames: u32,
+    /// Threshold violations
+    pub issues: Vec<QualityIssue>,
+}
+
+/// Keeps the state of the quality analysis between frames.
+#[derive(Default)]
+pub(crate) struct QualityAnalyzer {
+    frozen: IdenticalFrames,
+    latest: Option<QualityMetrics>,
+}
+
+/// Counts consecutive identical frames.
+#[derive(Default)]
+pub(crate) struct IdenticalFrames {
+    previous: Option<Mat>,
+    count: u32,
+}
+
+impl Quality {
+    /// Validates the quality analysis settings.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the first invalid setting.
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        let brightness = [self.min_brightness, self.max_brightness];
+        if brightness
+            .iter()
+            .flatten()
+            .any(|b| !b.is_finite() || !(0.0..=255.0).contains(b))
+        {
+            return Err("brightness thresholds must be in range 0-255".to_string());
+        }
+        if let (Some(min), Some(max)) = (self.min_brightness, self.max_brightness) {
+            if min > max {
+                return Err(format!(
+                    "minimum brightness {} exceeds maximum {}",
+                    min, max
+                ));
+            }
+        }
+        if self
+            .min_sharpness
+            .is_some_and(|s| !s.is_finite() || s < 0.0)
+        {
+            return Err("sharpness threshold must be finite and not negative".to_string());
+        }
+        if self.max_frozen_frames == Some(0) {
+            return Err("frozen frames threshold must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    fn check(&self, metrics: &QualityMetrics) -> Vec<QualityIssue> {
+        let mut issues = Vec::new();
+        if self
+            .min_sharpness
+            .is_some_and(|min| metrics.sharpness < min)
+        {
+            issues.push(QualityIssue::Blurry);
+        }
+        if self
+            .min_brightness
+            .is_some_and(|min| metrics.brightness < min)
+        {
+            issues.push(QualityIssue::TooDark);
+        }
+        if self
+            .max_brightness
+            .is_some_and(|max| metrics.brightness > max)
+        {
+            issues.push(QualityIssue::TooBright);
+        }
+        if self
+            .max_frozen_frames
+            .is_some_and(|max| metrics.frozen_frames >= max)
+        {
+            issues.push(QualityIssue::Frozen);
+        }
+        issues
+    }
+}
+
+impl QualityAnalyzer {
+    /// Analyzes a frame and checks it against the thresholds.
+    pub(crate) fn analyze(&mut self, frame: &Mat, config: &Quality) -> Result<&QualityMetrics> {
+        let size = frame.size()?;
+        let width = ANALYSIS_WIDTH.min(size.width);
+        let size = Size::new(width, size.height * width / size.width.max(1));
+        let mut small = Mat::default();
+        imgproc::resize(frame, &mut small, size, 0.0, 0.0, imgproc::INTER_AREA)?;
+        let gray = if small.channels() == 3 {
+            let mut gray = Mat::default();
+            imgproc::cvt_color_def(&small, &mut gray, imgproc::COLOR_BGR2GRAY)?;
+            gray
+        } else {
+            small
+        };
+
+        let mut laplacian = Mat::default();
+        imgproc::laplacian_def(&gray, &mut laplacian, core::CV_64F)?;
+        let mut mean = Scalar::default();
+        let mut stddev = Scalar::default();
+        core::mean_std_dev_def(&laplacian, &mut mean, &mut stddev)?;
+        let sharpness = stddev[0] * stddev[0];
+
+        let brightness = core::mean(&gray, &core::no_array())?[0];
+        let area = (size.width * size.height).max(1) as f64;
+        let mut clipped = Mat::default();
+        imgproc::threshold(
+            &gray,
+            &mut clipped,
+            DARK_LEVEL,
+            255.0,
+            imgproc::THRESH_BINARY_INV,
+        )?;
+        let dark_fraction = core::count_non_zero(&clipped)? as f64 / area;
+        imgproc::threshold(
+            &gray,
+            &mut clipped,
+            BRIGHT_LEVEL,
+            255.0,
+            imgproc::THRESH_BINARY,
+        )?;
+        let bright_fraction = core::count_non_zero(&clipped)? as f64 / area;
+
+        let frozen_frames = self.frozen.push(&gray);
+
+        let mut metrics = QualityMetrics {
+            timestamp: Local::now().to_rfc3339(),
+            sharpness,
+            brightness,
+            dark_fraction,
+            bright_fraction,
+            frozen_frames,
+            issues: Vec::new(),
+        };
+        metrics.issues = config.check(&metrics);
+        Ok(&*self.latest.insert(metrics))
+    }
+
+    /// Gets metrics of the latest analyzed frame.
+    pub(crate) fn latest(&self) -> Option<&QualityMetrics> {
+        self.latest.as_ref()
+    }
+}
+
+impl IdenticalFrames {
+    /// Records a frame and returns the number of consecutive frames identical to it.
+    pub(crate) fn push(&mut self, frame: &Mat) -> u32 {
+        let identical = self
+            .previous
+            .as_ref()
+            .is_some_and(|previous| is_identical(previous, frame));
+        self.count = if identical { self.count + 1 } else { 0 };
+        self.previous = frame.try_clone().ok();
+        self.count
+    }
+
+    /// Clears the frame history.
+    pub(crate) fn reset(&mut self) {
+        self.previous = None;
+        self.count = 0;
+    }
+}
+
+fn is_identical(previous: &Mat, frame: &Mat) -> bool {
+    let same_layout = previous.size().ok() == frame.size().ok() && previous.typ() == frame.typ();
+    same_layout
+        && core::norm2(previous, frame, core::NORM_INF, &core::no_array())
+            .is_ok_and(|norm| norm == 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_thresholds() {
+        let config = Quality {
+            min_sharpness: Some(100.0),
+            max_brightness: Some(200.0),
+            max_frozen_frames: Some(10),
+            ..Default::default()
+        };
+        let metrics = QualityMetrics {
+            timestamp: String::new(),
+            sharpness: 50.0,
+            brightness: 120.0,
+            dark_fraction: 0.0,
+            bright_fraction: 0.0,
+            frozen_frames: 10,
+            issues: Vec::new(),
+        };
+        assert_eq!(
+            config.check(&metrics),
+            vec![QualityIssue::Blurry, QualityIssue::Frozen]
+        );
+        let config = Quality {
+            min_sharpness: Some(f64::NAN),
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,257 @@
+//! Provides access to files captured into a data directory.
+
+use super::codec::Container;
+use super::{file, CaptureError};
+use chrono::{DateTime, Local};
+use log::{debug, error, warn};
+use opencv::{prelude::*, videoio};
+use serde::Serialize;
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+/// Defines kinds of captured files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileKind {
+    /// Captured frame
+    Image,
+    /// Recorded video
+    Video,
+    /// Any other file
+    Other,
+}
+
+impl FileKind {
+    /// Gets a file kind from a file extension.
+    pub fn from_extension(extension: &str) -> FileKind {
+        let extension = extension.to_ascii_lowercase();
+        if matches!(extension.as_str(), "png" | "jpg" | "jpeg") {
+            FileKind::Image
+        } else if Container::ALL
+            .iter()
+            .any(|container| container.file_extension() == extension)
+        {
+            FileKind::Video
+        } else {
+            FileKind::Other
+        }
+    }
+
+    /// Gets a file kind from a file path.
+    pub fn from_path(path: &Path) -> FileKind {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .map_or(FileKind::Other, FileKind::from_extension)
+    }
+}
+
+/// Defines a file stored in a data directory.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CapturedFile {
+    /// File path relative to the data directory with `/` separators, e.g. `run-1/frame.png`
+    pub name: String,
+    /// File size in bytes
+    pub size: u64,
+    /// File kind
+    pub kind: FileKind,
+    /// File creation time in RFC 3339 format
+    pub created: String,
+    /// Duration in seconds, only for videos
+    pub duration: Option<f64>,
+}
+
+/// Lists files stored in a data directory and its subdirectories sorted by name.
+pub fn list_files(data_dir: &Path) -> Result<Vec<CapturedFile>, CaptureError> {
+    let mut files = Vec::new();
+    collect_files(data_dir, Path::new(""), &mut files)?;
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(files)
+}
+
+/// Collects files stored in a subdirectory of a data directory and its subdirectories.
+fn collect_files(
+    data_dir: &Path,
+    subdir: &Path,
+    files: &mut Vec<CapturedFile>,
+) -> Result<(), CaptureError> {
+    let dir = data_dir.join(subdir);
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => {
+            error!("cannot read directory {}: {}", dir.display(), err);
+            return Err(CaptureError::FileAccess(dir.display().to_string()));
+        }
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let relative = subdir.join(entry.file_name());
+        let metadata = match entry.metadata() {
+            Ok(metadata) if metadata.is_dir() => {
+                collect_files(data_dir, &relative, files)?;
+                continue;
+            }
+            Ok(metadata) if metadata.is_file() && !file::is_partial(&path) => metadata,
+            _ => continue,
+        };
+        let Some(name) = relative_name(&relative) else {
+            continue;
+        };
+        let kind = FileKind::from_path(&path);
+        let created = metadata.created().or_else(|_| metadata.modified());
+        files.push(CapturedFile {
+            name,
+            size: metadata.len(),
+            kind,
+            created: created
+                .map(|time| DateTime::<Local>::from(time).to_rfc3339())
+                .unwrap_or_default(),
+            duration: match kind {
+                FileKind::Video => video_duration(&path),
+                _ => None,
+            },
+        });
+    }
+    Ok(())
+}
+
+/// Gets a relative path with `/` separators, `None` if it is not valid UTF-8.
+fn relative_name(path: &Path) -> Option<String> {
+    let parts = path
+        .components()
+        .map(|component| component.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()?;
+    Some(parts.join("/"))
+}
+
+/// Resolves a file name relative to a data directory, e.g. `run-1/frame.png`,
+/// to a path of an existing file inside the data directory. Names with other than plain
+/// path components, `\\` separators or resolving outside the data directory are rejected,
+/// as well as names of partial files still being written.
+pub fn resolve_file(data_dir: &Path, name: &str) -> Result<PathBuf, CaptureError> {
+    let path = Path::new(name);
+    let is_relative = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    if name.is_empty() || !is_relative || name.contains(['\\', ':']) || file::is_partial(path) {
+        return Err(CaptureError::InvalidFileName(name.to_string()));
+    }
+    let not_found = || CaptureError::FileNotFound(name.to_string());
+    let dir = fs::canonicalize(data_dir).map_err(|_| not_found())?;
+    let path = fs::canonicalize(dir.join(name)).map_err(|_| not_found())?;
+    if !path.starts_with(&dir) {
+        return Err(CaptureError::InvalidFileName(name.to_string()));
+    }
+    if !path.is_file() {
+        return Err(not_found());
+    }
+    Ok(path)
+}
+
+/// Deletes a file stored in a data directory.
+pub fn delete_file(data_dir: &Path, name: &str) -> Result<(), CaptureError> {
+    let path = resolve_file(data_dir, name)?;
+    if let Err(err) = fs::remove_file(&path) {
+        error!("cannot delete file {}: {}", path.display(), err);
+        return Err(CaptureError::FileAccess(name.to_string()));
+    }
+    debug!("file deleted: {}", path.display());
+    Ok(())
+}
+
+/// Removes partial files left over in a data directory and its subdirectories by interrupted captures.
+/// Meant to be called once at startup, before any capture is running. Returns the removed files.
+pub fn sweep_partial_files(data_dir: &Path) -> Vec<PathBuf> {
+    let mut removed = Vec::new();
+    let Ok(entries) = fs::read_dir(data_dir) else {
+        return removed;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            removed.extend(sweep_partial_files(&path));
+            continue;
+        }
+        if !file::is_partial(&path) {
+            continue;
+        }
+        match fs::remove_file(&path) {
+            Ok(()) => {
+                warn!(
+                    "removed partial file of an interrupted capture: {}",
+                    path.display()
+                );
+                removed.push(path);
+            }
+            Err(err) => warn!("cannot remove partial file {}: {}", path.display(), err),
+        }
+    }
+    removed
+}
+
+/// Gets a video duration in seconds from its frame count and frame rate.
+pub fn video_duration(path: &Path) -> Option<f64> {
+    let path = path.to_string_lossy();
+    let capture = videoio::VideoCapture::from_file(&path, videoio::CAP_ANY).ok()?;
+    if !capture.is_opened().unwrap_or(false) {
+        return None;
+    }
+    let frames = capture.get(videoio::CAP_PROP_FRAME_COUNT).ok()?;
+    let fps = capture.get(videoio::CAP_PROP_FPS).ok()?;
+    (frames > 0.0 && fps > 0.0).then(|| frames / fps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_file() {
+        let dir = std::env::temp_dir().join("icapture_storage_test");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("frame.png"), b"png").unwrap();
+        fs::write(dir.join("clip.partial.mp4"), b"mp4").unwrap();
+        fs::create_dir_all(dir.join("run-1")).unwrap();
+        fs::write(dir.join("run-1").join("frame.png"), b"png").unwrap();
+        let data_dir = dir.as_path();
+
+        assert!(resolve_file(data_dir, "frame.png").is_ok());
+        assert!(resolve_file(data_dir, "run-1/frame.png").is_ok());
+        assert!(matches!(
+            resolve_file(data_dir, "missing.png"),
+            Err(CaptureError::FileNotFound(_))
+        ));
+        assert!(matches!(
+            resolve_file(data_dir, "run-1"),
+            Err(CaptureError::FileNotFound(_))
+        ));
+        for name in [
+            "../frame.png",
+            "..",
+            "run-1/../frame.png",
+            "/frame.png",
+            "run-1\\frame.png",
+            "clip.partial.mp4",
+            "",
+        ] {
+            assert!(matches!(
+                resolve_file(data_dir, name),
+                Err(CaptureError::InvalidFileName(_))
+            ));
+        }
+        assert_eq!(FileKind::from_extension("MP4"), FileKind::Video);
+
+        let names: Vec<String> = list_files(data_dir)
+            .unwrap()
+            .into_iter()
+            .map(|file| file.name)
+            .collect();
+        assert_eq!(names, vec!["frame.png", "run-1/frame.png"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
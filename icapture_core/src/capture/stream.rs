@@ -0,0 +1,154 @@
+//! Provides live streaming of captured frames as JPEG images.
+
+use opencv::{core::Vector, imgcodecs, prelude::*, Result};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// Defines preview modes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PreviewMode {
+    /// Frames are shown in a window, which requires a display
+    #[default]
+    Window,
+    /// Frames are only served to the live stream and saved as `latest.jpg` in the data directory
+    Headless,
+}
+
+/// Defines live stream settings, viewers may request a lower frame rate or quality.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Stream {
+    /// Maximum frame rate of the stream
+    pub max_fps: u32,
+    /// Maximum JPEG quality of the stream frames, from 1 to 100
+    pub max_quality: u8,
+    /// Preview mode used if not chosen explicitly
+    pub preview_mode: PreviewMode,
+    /// Interval in seconds between refreshes of `latest.jpg` in headless preview,
+    /// the file is not written if not set
+    pub snapshot_interval: Option<f64>,
+}
+
+/// Holds the latest frame of a capturing device for live stream viewers.
+#[derive(Default)]
+pub(crate) struct LiveFrame {
+    frame: Option<(Instant, Mat)>,
+}
+
+impl Default for Stream {
+    fn default() -> Self {
+        Stream {
+            max_fps: 10,
+            max_quality: 80,
+            preview_mode: PreviewMode::Window,
+            snapshot_interval: Some(1.0),
+        }
+    }
+}
+
+impl PreviewMode {
+    /// Gets the preview mode name used in messages.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PreviewMode::Window => "window",
+            PreviewMode::Headless => "headless",
+        }
+    }
+}
+
+impl Stream {
+    /// Validates the stream settings.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the first invalid setting.
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        if self.max_fps == 0 {
+            return Err("stream max fps must be positive".to_string());
+        }
+        if !(1..=100).contains(&self.max_quality) {
+            return Err("stream max quality must be in range 1-100".to_string());
+        }
+        if self
+            .snapshot_interval
+            .is_some_and(|interval| !interval.is_finite() || interval <= 0.0)
+        {
+            return Err("stream snapshot interval must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    /// Limits a requested frame rate and JPEG quality to the stream settings.
+    pub fn limit(&self, fps: Option<u32>, quality: Option<u8>) -> (u32, u8) {
+        (
+            fps.unwrap_or(self.max_fps).clamp(1, self.max_fps),
+            quality
+                .unwrap_or(self.max_quality)
+                .clamp(1, self.max_quality),
+        )
+    }
+
+    /// Gets the interval between stream frames at the maximum frame rate.
+    pub fn frame_interval(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.max_fps.max(1) as f64)
+    }
+}
+
+impl LiveFrame {
+    /// Publishes a frame, unless the frame held is newer than the interval.
+    pub(crate) fn publish(&mut self, frame: &Mat, interval: Duration) {
+        if self
+            .frame
+            .as_ref()
+            .is_some_and(|(time, _)| time.elapsed() < interval)
+        {
+            return;
+        }
+        if let Ok(frame) = frame.try_clone() {
+            self.frame = Some((Instant::now(), frame));
+        }
+    }
+
+    /// Gets a copy of the frame held, if it is newer than the maximum age.
+    pub(crate) fn latest(&self, max_age: Duration) -> Option<Mat> {
+        self.frame
+            .as_ref()
+            .filter(|(time, _)| time.elapsed() < max_age)
+            .and_then(|(_, frame)| frame.try_clone().ok())
+    }
+}
+
+/// Encodes a frame as a JPEG image with a given quality.
+pub fn encode_jpeg(frame: &Mat, quality: u8) -> Result<Vec<u8>> {
+    let mut buffer = Vector::<u8>::new();
+    let params = Vector::from_slice(&[imgcodecs::IMWRITE_JPEG_QUALITY, quality as i32]);
+    imgcodecs::imencode(".jpg", frame, &mut buffer, &params)?;
+    Ok(buffer.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit() {
+        let stream = Stream::default();
+        assert_eq!(stream.limit(None, None), (10, 80));
+        assert_eq!(stream.limit(Some(30), Some(50)), (10, 50));
+        assert_eq!(stream.limit(Some(0), Some(0)), (1, 1));
+        assert!(Stream {
+            max_quality: 0,
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn test_preview_mode() {
+        assert_eq!(serde_json::to_value(PreviewMode::Window).unwrap(), "Window");
+        let mode: PreviewMode = serde_json::from_str("\"Headless\"").unwrap();
+        assert_eq!(mode, PreviewMode::Headless);
+        assert!(serde_json::from_str::<PreviewMode>("\"headless\"").is_err());
+    }
+}
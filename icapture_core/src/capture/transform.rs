@@ -0,0 +1,192 @@
+//! Provides geometric transformations of captured frames - cropping, scaling, rotation and flipping.
+
+use opencv::{
+    core::{self, Rect, Size},
+    imgproc,
+    prelude::*,
+    Result,
+};
+use serde::{Deserialize, Serialize};
+
+/// Defines a rectangular region of a frame in pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Region {
+    /// Left edge
+    pub x: u32,
+    /// Top edge
+    pub y: u32,
+    /// Region width
+    pub width: u32,
+    /// Region height
+    pub height: u32,
+}
+
+/// Defines a target frame size in pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FrameSize {
+    /// Frame width
+    pub width: u32,
+    /// Frame height
+    pub height: u32,
+}
+
+/// Defines clockwise frame rotations.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Rotation {
+    /// No rotation
+    #[default]
+    None,
+    /// Rotation by 90 degrees
+    Rotate90,
+    /// Rotation by 180 degrees
+    Rotate180,
+    /// Rotation by 270 degrees
+    Rotate270,
+}
+
+/// Defines frame mirroring.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Flip {
+    /// No mirroring
+    #[default]
+    None,
+    /// Mirroring around the vertical axis
+    Horizontal,
+    /// Mirroring around the horizontal axis
+    Vertical,
+    /// Mirroring around both axes
+    Both,
+}
+
+/// Defines a frame transformation applied in order: crop, resize, rotate, flip.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Transform {
+    /// Region of interest to crop frames to
+    pub crop: Option<Region>,
+    /// Size to scale cropped frames to
+    pub resize: Option<FrameSize>,
+    /// Rotation of scaled frames
+    pub rotation: Rotation,
+    /// Mirroring of rotated frames
+    pub flip: Flip,
+}
+
+impl Transform {
+    /// Validates the transformation against a specific input frame size.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the first invalid setting.
+    pub fn validate(&self, frame_size: (u32, u32)) -> std::result::Result<(), String> {
+        if let Some(crop) = self.crop {
+            if crop.width == 0 || crop.height == 0 {
+                return Err("crop region must not be empty".to_string());
+            }
+            let fits = |offset: u32, length: u32, limit: u32| {
+                offset.checked_add(length).is_some_and(|end| end <= limit)
+            };
+            if !fits(crop.x, crop.width, frame_size.0) || !fits(crop.y, crop.height, frame_size.1) {
+                return Err(format!(
+                    "crop region {:?} is outside the {}x{} frame",
+                    crop, frame_size.0, frame_size.1
+                ));
+            }
+        }
+        if let Some(resize) = self.resize {
+            if resize.width == 0 || resize.height == 0 {
+                return Err("resize target must not be empty".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Gets the size of frames produced by the transformation for a specific input frame size.
+    pub fn output_size(&self, frame_size: (u32, u32)) -> (u32, u32) {
+        let mut size = frame_size;
+        if let Some(crop) = self.crop {
+            size = (crop.width, crop.height);
+        }
+        if let Some(resize) = self.resize {
+            size = (resize.width, resize.height);
+        }
+        match self.rotation {
+            Rotation::Rotate90 | Rotation::Rotate270 => (size.1, size.0),
+            Rotation::None | Rotation::Rotate180 => size,
+        }
+    }
+
+    /// Applies the transformation to a frame.
+    pub(crate) fn apply(&self, mut frame: Mat) -> Result<Mat> {
+        if let Some(crop) = self.crop {
+            let rect = Rect::new(
+                crop.x as i32,
+                crop.y as i32,
+                crop.width as i32,
+                crop.height as i32,
+            );
+            frame = Mat::roi(&frame, rect)?.try_clone()?;
+        }
+        if let Some(resize) = self.resize {
+            let mut resized = Mat::default();
+            let size = Size::new(resize.width as i32, resize.height as i32);
+            imgproc::resize(&frame, &mut resized, size, 0.0, 0.0, imgproc::INTER_AREA)?;
+            frame = resized;
+        }
+        let rotate_code = match self.rotation {
+            Rotation::None => None,
+            Rotation::Rotate90 => Some(core::ROTATE_90_CLOCKWISE),
+            Rotation::Rotate180 => Some(core::ROTATE_180),
+            Rotation::Rotate270 => Some(core::ROTATE_90_COUNTERCLOCKWISE),
+        };
+        if let Some(rotate_code) = rotate_code {
+            let mut rotated = Mat::default();
+            core::rotate(&frame, &mut rotated, rotate_code)?;
+            frame = rotated;
+        }
+        let flip_code = match self.flip {
+            Flip::None => None,
+            Flip::Horizontal => Some(1),
+            Flip::Vertical => Some(0),
+            Flip::Both => Some(-1),
+        };
+        if let Some(flip_code) = flip_code {
+            let mut flipped = Mat::default();
+            core::flip(&frame, &mut flipped, flip_code)?;
+            frame = flipped;
+        }
+        Ok(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_size() {
+        let transform = Transform {
+            crop: Some(Region {
+                x: 100,
+                y: 100,
+                width: 800,
+                height: 600,
+            }),
+            rotation: Rotation::Rotate90,
+            ..Default::default()
+        };
+        assert!(transform.validate((1920, 1080)).is_ok());
+        assert_eq!(transform.output_size((1920, 1080)), (600, 800));
+        assert!(transform.validate((640, 480)).is_err());
+        let transform = Transform {
+            crop: Some(Region {
+                x: u32::MAX,
+                y: 0,
+                width: 2,
+                height: 2,
+            }),
+            ..Default::default()
+        };
+        assert!(transform.validate((1920, 1080)).is_err());
+    }
+}
@@ -0,0 +1,171 @@
+//! Provides a watchdog detecting lost or frozen capturing streams and the reconnection policy.
+
+use crate::capture::quality::IdenticalFrames;
+use chrono::Local;
+use opencv::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Defines stream watchdog settings.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Watchdog {
+    /// Reconnect the capturing device when the stream is lost
+    pub enabled: bool,
+    /// Number of consecutive failed reads after which the stream is considered lost
+    pub max_failed_reads: u32,
+    /// Number of consecutive identical frames after which the stream is considered frozen,
+    /// not checked if not set since a static digital source may produce identical frames
+    pub max_identical_reads: Option<u32>,
+    /// Delay in seconds before the first reconnection attempt, doubled after each failed attempt
+    pub initial_backoff: f64,
+    /// Maximum delay in seconds between reconnection attempts
+    pub max_backoff: f64,
+    /// Maximum number of reconnection attempts, unlimited if not set
+    pub max_attempts: Option<u32>,
+}
+
+/// Defines reconnection event kinds.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub enum ReconnectEventKind {
+    /// The stream has been lost
+    Disconnected,
+    /// The capturing device has been reopened and capturing resumed
+    Reconnected,
+    /// The capturing device could not be reopened and capturing stopped
+    Failed,
+}
+
+/// Defines a reconnection event.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ReconnectEvent {
+    /// Local time of the event in RFC 3339 format
+    pub timestamp: String,
+    /// Event kind
+    pub kind: ReconnectEventKind,
+    /// Reason the stream has been considered lost
+    pub reason: String,
+    /// Number of reconnection attempts made so far
+    pub attempts: u32,
+    /// Path to the video file recording resumed into, if any
+    pub file_path: Option<PathBuf>,
+}
+
+/// Keeps track of consecutive failed and identical reads.
+pub(crate) struct StreamWatchdog {
+    config: Watchdog,
+    failed_reads: u32,
+    identical_frames: IdenticalFrames,
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Watchdog {
+            enabled: true,
+            max_failed_reads: 30,
+            max_identical_reads: None,
+            initial_backoff: 1.0,
+            max_backoff: 30.0,
+            max_attempts: None,
+        }
+    }
+}
+
+impl Watchdog {
+    /// Validates the watchdog settings.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the first invalid setting.
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        if self.max_failed_reads == 0 || self.max_identical_reads == Some(0) {
+            return Err("watchdog read thresholds must be positive".to_string());
+        }
+        if !(self.initial_backoff > 0.0 && self.initial_backoff <= self.max_backoff) {
+            return Err(format!(
+                "watchdog backoff must be positive and at most {} seconds",
+                self.max_backoff
+            ));
+        }
+        if !self.max_backoff.is_finite() {
+            return Err("watchdog maximum backoff must be finite".to_string());
+        }
+        Ok(())
+    }
+
+    /// Gets the delay before a specific reconnection attempt, starting from 1.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2_f64.powi(attempt.saturating_sub(1).min(30) as i32);
+        Duration::from_secs_f64((self.initial_backoff * factor).min(self.max_backoff))
+    }
+}
+
+impl ReconnectEvent {
+    pub(crate) fn new(
+        kind: ReconnectEventKind,
+        reason: &str,
+        attempts: u32,
+        file_path: Option<PathBuf>,
+    ) -> Self {
+        ReconnectEvent {
+            timestamp: Local::now().to_rfc3339(),
+            kind,
+            reason: reason.to_string(),
+            attempts,
+            file_path,
+        }
+    }
+}
+
+impl StreamWatchdog {
+    pub(crate) fn new(config: &Watchdog) -> Self {
+        StreamWatchdog {
+            config: *config,
+            failed_reads: 0,
+            identical_frames: IdenticalFrames::default(),
+        }
+    }
+
+    /// Records a read result, `None` for a failed read.
+    /// Returns the reason if the stream is considered lost.
+    pub(crate) fn check(&mut self, frame: Option<&Mat>) -> Option<String> {
+        if !self.config.enabled {
+            return None;
+        }
+        let Some(frame) = frame else {
+            self.failed_reads += 1;
+            return (self.failed_reads >= self.config.max_failed_reads)
+                .then(|| format!("{} consecutive failed reads", self.failed_reads));
+        };
+        self.failed_reads = 0;
+
+        let max_identical_reads = self.config.max_identical_reads?;
+        let identical_reads = self.identical_frames.push(frame);
+        (identical_reads >= max_identical_reads)
+            .then(|| format!("{} consecutive identical frames", identical_reads))
+    }
+
+    /// Clears the read history, e.g. after the device has been reopened.
+    pub(crate) fn reset(&mut self) {
+        self.failed_reads = 0;
+        self.identical_frames.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_is_capped() {
+        let watchdog = Watchdog {
+            initial_backoff: 0.5,
+            max_backoff: 3.0,
+            ..Default::default()
+        };
+        assert_eq!(watchdog.backoff(1), Duration::from_millis(500));
+        assert_eq!(watchdog.backoff(3), Duration::from_secs(2));
+        assert_eq!(watchdog.backoff(100), Duration::from_secs(3));
+    }
+}
@@ -2,11 +2,27 @@
 
 use log::{debug, warn};
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::fs::File;
 use std::io;
 use std::io::BufReader;
+use std::path::{Path, PathBuf};
 
-use crate::capture::codec::Codec;
+use crate::capture::codec::{Codec, CodecParams, Container};
+use crate::capture::color::ColorMode;
+use crate::capture::controls::Controls;
+use crate::capture::mask::Mask;
+use crate::capture::motion::Motion;
+use crate::capture::naming::Naming;
+use crate::capture::overlay::Overlay;
+use crate::capture::quality::Quality;
+use crate::capture::stream::Stream;
+use crate::capture::transform::Transform;
+use crate::capture::watchdog::Watchdog;
+use crate::preset::Preset;
+
+/// Name of the default data directory outside Windows.
+const DATA_DIR_NAME: &str = "icapture";
 
 /// Defines a configuration object.
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
@@ -19,10 +35,53 @@ pub struct Config {
     pub frame_width: u32,
     /// Desired frame height
     pub frame_height: u32,
-    /// Path to store files at
-    pub data_dir: String,
+    /// Path to store files at, the platform data directory if not set
+    #[serde(default = "default_data_dir")]
+    pub data_dir: PathBuf,
+    /// Naming of captured files
+    #[serde(default)]
+    pub naming: Naming,
     /// Desired codec for saving video
     pub codec: Codec,
+    /// Desired container for saving video, the codec's default container if not set
+    #[serde(default)]
+    pub container: Option<Container>,
+    /// Desired codec parameters for saving video
+    #[serde(default)]
+    pub codec_params: CodecParams,
+    /// Codecs to try in order if the desired codec cannot be opened
+    #[serde(default)]
+    pub codec_fallback: Vec<Codec>,
+    /// Desired color mode of saved frames and video
+    #[serde(default)]
+    pub color_mode: ColorMode,
+    /// Transformation applied to captured frames
+    #[serde(default)]
+    pub transform: Transform,
+    /// Text overlay drawn on captured frames
+    #[serde(default)]
+    pub overlay: Overlay,
+    /// Privacy masks applied to captured frames before the transformation
+    #[serde(default)]
+    pub masks: Vec<Mask>,
+    /// Motion detection settings
+    #[serde(default)]
+    pub motion: Motion,
+    /// Frame quality analysis settings
+    #[serde(default)]
+    pub quality: Quality,
+    /// Stream watchdog settings
+    #[serde(default)]
+    pub watchdog: Watchdog,
+    /// Live stream settings
+    #[serde(default)]
+    pub stream: Stream,
+    /// Camera controls applied when the capturing device is opened
+    #[serde(default)]
+    pub controls: Controls,
+    /// Name of the camera control preset applied before the camera controls
+    #[serde(default)]
+    pub preset: Option<String>,
 }
 
 /// Defines possible serialization errors.
@@ -32,8 +91,22 @@ pub enum ConfigError {
     Io(io::Error),
     /// JSON error
     Json(serde_json::Error),
+    /// Preset name is not a plain name
+    InvalidPresetName(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(f, "i/o error: {}", err),
+            ConfigError::Json(err) => write!(f, "json error: {}", err),
+            ConfigError::InvalidPresetName(name) => write!(f, "invalid preset name '{}'", name),
+        }
+    }
 }
 
+impl std::error::Error for ConfigError {}
+
 impl From<io::Error> for ConfigError {
     fn from(err: io::Error) -> ConfigError {
         ConfigError::Io(err)
@@ -53,31 +126,93 @@ impl Default for Config {
             fps: 30,
             frame_width: 1920,
             frame_height: 1080,
-            data_dir: String::from("c:\\icapture_data"),
+            data_dir: default_data_dir(),
+            naming: Naming::default(),
             codec: Codec::H264,
+            container: None,
+            codec_params: CodecParams::default(),
+            codec_fallback: Vec::new(),
+            color_mode: ColorMode::BGR,
+            transform: Transform::default(),
+            overlay: Overlay::default(),
+            masks: Vec::new(),
+            motion: Motion::default(),
+            quality: Quality::default(),
+            watchdog: Watchdog::default(),
+            stream: Stream::default(),
+            controls: Controls::default(),
+            preset: None,
         }
     }
 }
 
 impl Config {
     /// Constructor for a configuration object.
+    /// Presets are looked up in the directory of the configuration file.
     pub fn new(file_path: &str) -> Self {
         match Self::load_from_file(file_path) {
-            Ok(config) => {
+            Ok(mut config) => {
+                if let Err(err) = config.apply_preset(&Preset::dir(file_path)) {
+                    warn!("cannot apply preset {:?}: {}", config.preset, err);
+                }
                 debug!("using config {:?}", &config);
                 config
             }
             Err(_) => {
                 warn!("cannot read config file '{file_path}'");
-                warn!(
-                    "falling back to default config {:?}",
-                    Self::default()
-                );
+                warn!("falling back to default config {:?}", Self::default());
                 Self::default()
             }
         }
     }
 
+    /// Validates the configuration.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the first invalid setting.
+    pub fn validate(&self) -> Result<(), String> {
+        self.codec_params.validate(self.codec)?;
+        self.naming.validate()?;
+        self.overlay.validate()?;
+        self.masks.iter().try_for_each(Mask::validate)?;
+        self.motion.validate()?;
+        self.quality.validate()?;
+        self.watchdog.validate()?;
+        self.stream.validate()?;
+        self.controls.validate()
+    }
+
+    /// Merges the configured preset, if any, from a specific directory.
+    /// The frame rate and frame size of the preset replace the configured ones,
+    /// while camera controls set in the configuration take precedence over the preset.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the preset cannot be loaded.
+    pub fn apply_preset(&mut self, dir: &Path) -> Result<(), ConfigError> {
+        if let Some(name) = &self.preset {
+            let preset = Preset::load(dir, name)?;
+            if let Some(fps) = preset.fps {
+                self.fps = fps;
+            }
+            if let Some((width, height)) = preset.frame_size() {
+                self.frame_width = width;
+                self.frame_height = height;
+            }
+            let mut controls = preset.controls;
+            controls.merge(&self.controls);
+            self.controls = controls;
+        }
+        Ok(())
+    }
+
+    /// Gets the container for saving video.
+    pub fn video_container(&self) -> Container {
+        self.container
+            .unwrap_or_else(|| self.codec.default_container())
+    }
+
     fn load_from_file(file_path: &str) -> Result<Self, ConfigError> {
         let reader = BufReader::new(File::open(file_path)?);
         let config = serde_json::from_reader(reader)?;
@@ -85,11 +220,33 @@ impl Config {
     }
 }
 
+/// Gets the default path to store files at - `c:\icapture_data` on Windows,
+/// `icapture` in the XDG data directory on Linux and in the application support directory on macOS.
+fn default_data_dir() -> PathBuf {
+    if cfg!(windows) {
+        return PathBuf::from("c:\\icapture_data");
+    }
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let data_home = if cfg!(target_os = "macos") {
+        home.map(|home| home.join("Library").join("Application Support"))
+    } else {
+        std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .or_else(|| home.map(|home| home.join(".local").join("share")))
+    };
+    data_home
+        .unwrap_or_else(std::env::temp_dir)
+        .join(DATA_DIR_NAME)
+}
+
 #[cfg(test)]
 mod tests {
     use std::fs;
 
     use super::*;
+    use crate::capture::mask::{MaskFill, MaskShape};
+    use crate::capture::transform::Rotation;
 
     #[test]
     fn test_from_valid_file_succeeds() {
@@ -98,8 +255,55 @@ mod tests {
             fps: 42,
             frame_width: 2560,
             frame_height: 1440,
-            data_dir: "test directory".to_string(),
+            data_dir: PathBuf::from("test directory"),
+            naming: Naming {
+                template: Some("{device}_{seq}".to_string()),
+                subdir: Some("frames".to_string()),
+            },
             codec: Codec::H264,
+            container: Some(Container::MKV),
+            codec_params: CodecParams {
+                quality: Some(90),
+                ..Default::default()
+            },
+            codec_fallback: vec![Codec::MJPG, Codec::RAW],
+            color_mode: ColorMode::GRAY,
+            transform: Transform {
+                rotation: Rotation::Rotate180,
+                ..Default::default()
+            },
+            overlay: Overlay {
+                enabled: true,
+                label: Some("test label".to_string()),
+                ..Default::default()
+            },
+            masks: vec![Mask {
+                shape: MaskShape::Polygon(vec![[0.0, 0.0], [0.5, 0.0], [0.0, 0.5]]),
+                fill: MaskFill::Pixelate(8),
+            }],
+            motion: Motion {
+                record: false,
+                ..Default::default()
+            },
+            quality: Quality {
+                min_brightness: Some(10.0),
+                fail_grab: true,
+                ..Default::default()
+            },
+            watchdog: Watchdog {
+                max_attempts: Some(5),
+                ..Default::default()
+            },
+            stream: Stream {
+                max_fps: 5,
+                ..Default::default()
+            },
+            controls: Controls {
+                auto_exposure: Some(false),
+                exposure: Some(-6.0),
+                ..Default::default()
+            },
+            preset: None,
         };
         let json = serde_json::to_string(&config).unwrap();
         let file_path = "test_config.json";
@@ -109,6 +313,13 @@ mod tests {
         fs::remove_file(file_path).unwrap();
     }
 
+    #[test]
+    fn test_default_data_dir() {
+        let json = r#"{"device_id": 0, "fps": 30, "frame_width": 640, "frame_height": 480, "codec": "H264"}"#;
+        let config: Config = serde_json::from_str(json).unwrap();
+        assert_eq!(config.data_dir, default_data_dir());
+    }
+
     #[test]
     fn test_from_invalid_file_defaults() {
         let file_path = "invalid_test_config.json";
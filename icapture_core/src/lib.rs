@@ -5,10 +5,31 @@
 #![deny(missing_docs)]
 
 pub use self::capture::Capture;
+pub use self::capture::CaptureArtifact;
 pub use self::capture::CaptureError;
+pub use self::capture::CaptureStatus;
+pub use self::capture::FrameSkew;
+pub use self::capture::VideoOutput;
 pub use self::capture::codec;
+pub use self::capture::color;
+pub use self::capture::composite;
+pub use self::capture::controls;
 pub use self::capture::device;
+pub use self::capture::events;
+pub use self::capture::mask;
+pub use self::capture::motion;
+pub use self::capture::multi;
+pub use self::capture::multi::MultiCapture;
+pub use self::capture::naming;
+pub use self::capture::overlay;
+pub use self::capture::quality;
+pub use self::capture::storage;
+pub use self::capture::stream;
+pub use self::capture::transform;
+pub use self::capture::watchdog;
 pub use self::config::Config;
+pub use self::preset::Preset;
 
 pub mod capture;
 pub mod config;
+pub mod preset;
@@ -0,0 +1,151 @@
+//! Provides operations for saving and restoring named presets of device properties.
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use crate::capture::controls::Controls;
+use crate::config::ConfigError;
+
+const PRESET_EXTENSION: &str = ".preset.json";
+
+/// Defines a named set of device properties - frame rate, frame size and camera control values.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Preset {
+    /// Preset name
+    pub name: String,
+    /// Frame rate, left unchanged if not set
+    pub fps: Option<u32>,
+    /// Frame width, the frame size is left unchanged if width or height is not set
+    pub frame_width: Option<u32>,
+    /// Frame height, the frame size is left unchanged if width or height is not set
+    pub frame_height: Option<u32>,
+    /// Camera control values
+    pub controls: Controls,
+}
+
+impl Preset {
+    /// Constructor for a preset object with camera control values only.
+    pub fn new(name: &str, controls: Controls) -> Self {
+        Preset {
+            name: name.to_string(),
+            fps: None,
+            frame_width: None,
+            frame_height: None,
+            controls,
+        }
+    }
+
+    /// Gets the directory presets are stored in, next to a configuration file.
+    pub fn dir(config_file: &str) -> PathBuf {
+        match Path::new(config_file).parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        }
+    }
+
+    /// Gets the frame size, if both width and height are set.
+    pub fn frame_size(&self) -> Option<(u32, u32)> {
+        self.frame_width.zip(self.frame_height)
+    }
+
+    /// Gets the path of a preset file, presets are stored as `<name>.preset.json`.
+    pub fn file_path(dir: &Path, name: &str) -> PathBuf {
+        dir.join(format!("{}{}", name, PRESET_EXTENSION))
+    }
+
+    /// Loads a preset by name from a directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an invalid name error if the name is invalid, an I/O error if the file
+    /// cannot be read, or a JSON error if the file content is invalid.
+    pub fn load(dir: &Path, name: &str) -> Result<Self, ConfigError> {
+        Self::validate_name(name)?;
+        let file_path = Self::file_path(dir, name);
+        let reader = BufReader::new(File::open(&file_path)?);
+        let preset: Preset = serde_json::from_reader(reader)?;
+        debug!("preset loaded from '{}'", file_path.display());
+        Ok(Preset {
+            name: name.to_string(),
+            ..preset
+        })
+    }
+
+    /// Saves the preset to a directory, overwriting a preset with the same name.
+    ///
+    /// # Errors
+    ///
+    /// Returns an invalid name error if the name is invalid,
+    /// or an I/O error if the file cannot be written.
+    pub fn save(&self, dir: &Path) -> Result<PathBuf, ConfigError> {
+        Self::validate_name(&self.name)?;
+        let file_path = Self::file_path(dir, &self.name);
+        let writer = BufWriter::new(File::create(&file_path)?);
+        serde_json::to_writer_pretty(writer, self)?;
+        debug!("preset saved to '{}'", file_path.display());
+        Ok(file_path)
+    }
+
+    /// Lists names of the presets stored in a directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the directory cannot be read.
+    pub fn list(dir: &Path) -> Result<Vec<String>, ConfigError> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let file_name = entry?.file_name();
+            if let Some(name) = file_name.to_string_lossy().strip_suffix(PRESET_EXTENSION) {
+                names.push(name.to_string());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    fn validate_name(name: &str) -> Result<(), ConfigError> {
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(ConfigError::InvalidPresetName(name.to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_load_list() {
+        let dir = std::env::temp_dir().join("icapture_test_presets");
+        fs::create_dir_all(&dir).unwrap();
+        let preset = Preset {
+            fps: Some(30),
+            frame_width: Some(1280),
+            frame_height: Some(720),
+            ..Preset::new(
+                "lab-led",
+                Controls {
+                    white_balance: Some(4500.0),
+                    ..Default::default()
+                },
+            )
+        };
+        preset.save(&dir).unwrap();
+        assert_eq!(Preset::load(&dir, "lab-led").unwrap(), preset);
+        assert_eq!(Preset::list(&dir).unwrap(), vec!["lab-led".to_string()]);
+        assert!(matches!(
+            Preset::load(&dir, "../lab-led"),
+            Err(ConfigError::InvalidPresetName(_))
+        ));
+        assert_eq!(Preset::dir("config.json"), PathBuf::from("."));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
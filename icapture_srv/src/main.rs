@@ -1,17 +1,39 @@
-use icapture_core::{Capture, Config};
+use icapture_core::{
+    codec, composite::Composite, naming::Naming, storage, CaptureError, Config, Preset,
+};
 use log::warn;
+use percent_encoding::percent_decode_str;
+use std::collections::BTreeMap;
 use std::sync::{Arc, Mutex};
 use tokio::runtime::Runtime;
+use tokio::sync::broadcast;
 use warp::{Filter, Rejection};
 
 mod server;
 use server::*;
 
+const EVENT_CAPACITY: usize = 256;
+/// Maximum size of a file naming request body in bytes.
+const NAMING_BODY_LIMIT: u64 = 4 * 1024;
+/// Server configuration file used if no path is given as the first argument.
+const CONFIG_FILE: &str = "config.json";
+
 fn main() {
     env_logger::builder().format_timestamp_millis().init();
+    let config_file = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| CONFIG_FILE.to_string());
+    let config = Config::new(&config_file);
+    codec::init_writer_options(&config.codec_params);
+    storage::sweep_partial_files(&config.data_dir);
+    let settings: SettingsState = Arc::new(Settings {
+        preset_dir: Preset::dir(&config_file),
+        data_dir: config.data_dir.clone(),
+    });
     let log = warp::log("icapture_srv::api");
 
-    let state = Arc::new(Mutex::new(None::<Capture>));
+    let state: CaptureState = Arc::new(Mutex::new(BTreeMap::new()));
+    let (events, _) = broadcast::channel(EVENT_CAPACITY);
 
     let list = warp::get().and(warp::path("list")).and_then(list_devices);
 
@@ -19,43 +41,281 @@ fn main() {
         .and(warp::path("init"))
         .and(warp::body::json().or_else(|_| async {
             warn!("cannot parse request body, falling back to default config");
-            Ok::<(Config,), Rejection>((Config::default(),))
+            Ok::<(InitRequest,), Rejection>((InitRequest::Single(Config::default()),))
         }))
         .and(with_state(state.clone()))
+        .and(with_events(events.clone()))
+        .and(with_settings(settings.clone()))
         .and_then(init_capture);
 
     let preview = warp::post()
         .and(warp::path("preview"))
+        .and(with_default_session())
+        .and(warp::query())
         .and(with_state(state.clone()))
         .and_then(preview);
 
+    let stream = warp::get()
+        .and(warp::path!("stream.mjpg"))
+        .and(with_default_session())
+        .and(warp::query())
+        .and(with_state(state.clone()))
+        .and_then(stream_mjpeg);
+
+    let websocket = warp::get()
+        .and(warp::path!("ws"))
+        .and(with_default_session())
+        .and(warp::ws())
+        .and(warp::query())
+        .and(with_state(state.clone()))
+        .and(with_events(events.clone()))
+        .and_then(stream_websocket);
+
+    let events_stream = warp::get()
+        .and(warp::path!("events"))
+        .map(|| None)
+        .and(with_events(events.clone()))
+        .and_then(stream_events);
+
     let grab = warp::post()
         .and(warp::path("frame"))
+        .and(with_default_session())
+        .and(with_naming())
         .and(with_state(state.clone()))
         .and_then(grab_frame);
 
     let start = warp::post()
         .and(warp::path("start"))
+        .and(with_default_session())
+        .and(with_naming())
         .and(with_state(state.clone()))
         .and_then(start_grab_video);
 
     let stop = warp::post()
         .and(warp::path("stop"))
+        .and(with_default_session())
         .and(with_state(state.clone()))
         .and_then(stop_grab_video);
 
+    let composite = warp::post()
+        .and(warp::path("composite"))
+        .and(with_default_session())
+        .and(
+            warp::body::json()
+                .or_else(|_| async { Ok::<(Composite,), Rejection>((Composite::default(),)) }),
+        )
+        .and(with_state(state.clone()))
+        .and_then(start_grab_composite);
+
+    let motion_start = warp::post()
+        .and(warp::path!("motion" / "start"))
+        .and(with_default_session())
+        .and(with_state(state.clone()))
+        .and_then(start_motion_monitor);
+
+    let motion_stop = warp::post()
+        .and(warp::path!("motion" / "stop"))
+        .and(with_default_session())
+        .and(with_state(state.clone()))
+        .and_then(stop_motion_monitor);
+
+    let motion = warp::get()
+        .and(warp::path!("motion"))
+        .and(with_default_session())
+        .and(with_state(state.clone()))
+        .and_then(motion_events);
+
+    let controls = warp::put()
+        .and(warp::path("controls"))
+        .and(with_default_session())
+        .and(warp::body::json())
+        .and(with_state(state.clone()))
+        .and_then(set_controls);
+
+    let presets = warp::get()
+        .and(warp::path!("presets"))
+        .and(with_settings(settings.clone()))
+        .and_then(list_presets);
+
+    let preset_save = warp::post()
+        .and(with_default_session())
+        .and(warp::path!("presets" / String))
+        .and(with_state(state.clone()))
+        .and(with_settings(settings.clone()))
+        .and_then(save_preset);
+
+    let preset_apply = warp::put()
+        .and(with_default_session())
+        .and(warp::path!("presets" / String))
+        .and(with_state(state.clone()))
+        .and(with_settings(settings.clone()))
+        .and_then(apply_preset);
+
+    let files = warp::get()
+        .and(warp::path!("files"))
+        .and(with_default_session())
+        .and(with_state(state.clone()))
+        .and(with_settings(settings.clone()))
+        .and_then(list_files);
+
+    let file_download = warp::get()
+        .and(with_default_session())
+        .and(warp::path!("files" / ..))
+        .and(with_file_name())
+        .and(warp::header::optional::<String>("range"))
+        .and(with_state(state.clone()))
+        .and(with_settings(settings.clone()))
+        .and_then(download_file);
+
+    let file_delete = warp::delete()
+        .and(with_default_session())
+        .and(warp::path!("files" / ..))
+        .and(with_file_name())
+        .and(with_state(state.clone()))
+        .and(with_settings(settings.clone()))
+        .and_then(delete_file);
+
+    let skew = warp::get()
+        .and(warp::path("skew"))
+        .and(with_default_session())
+        .and(with_state(state.clone()))
+        .and_then(frame_skew);
+
+    let reconnects = warp::get()
+        .and(warp::path("reconnects"))
+        .and(with_default_session())
+        .and(with_state(state.clone()))
+        .and_then(reconnect_events);
+
+    let status = warp::get()
+        .and(warp::path("status"))
+        .and(with_default_session())
+        .and(with_state(state.clone()))
+        .and_then(status);
+
     let dispose = warp::post()
         .and(warp::path("deinit"))
+        .and(with_default_session())
         .and(with_state(state.clone()))
         .and_then(dispose_capture);
 
+    let session_create = warp::post()
+        .and(warp::path!("sessions"))
+        .and(warp::body::json())
+        .and(with_state(state.clone()))
+        .and(with_events(events.clone()))
+        .and(with_settings(settings.clone()))
+        .and_then(create_session);
+
+    let sessions = warp::get()
+        .and(warp::path!("sessions"))
+        .and(with_state(state.clone()))
+        .and_then(list_sessions);
+
+    let session = warp::get()
+        .and(warp::path!("sessions" / String))
+        .and(with_state(state.clone()))
+        .and_then(get_session);
+
+    let session_delete = warp::delete()
+        .and(warp::path!("sessions" / String))
+        .and(with_state(state.clone()))
+        .and_then(dispose_capture);
+
+    let session_stream = warp::get()
+        .and(warp::path!("sessions" / String / "stream.mjpg"))
+        .and(warp::query())
+        .and(with_state(state.clone()))
+        .and_then(stream_mjpeg);
+
+    let session_websocket = warp::get()
+        .and(warp::path!("sessions" / String / "ws"))
+        .and(warp::ws())
+        .and(warp::query())
+        .and(with_state(state.clone()))
+        .and(with_events(events.clone()))
+        .and_then(stream_websocket);
+
+    let session_events = warp::get()
+        .and(warp::path!("sessions" / String / "events").map(Some))
+        .and(with_events(events.clone()))
+        .and_then(stream_events);
+
+    let session_grab = warp::post()
+        .and(warp::path!("sessions" / String / "frame"))
+        .and(with_naming())
+        .and(with_state(state.clone()))
+        .and_then(grab_frame);
+
+    let session_start = warp::post()
+        .and(warp::path!("sessions" / String / "start"))
+        .and(with_naming())
+        .and(with_state(state.clone()))
+        .and_then(start_grab_video);
+
+    let session_stop = warp::post()
+        .and(warp::path!("sessions" / String / "stop"))
+        .and(with_state(state.clone()))
+        .and_then(stop_grab_video);
+
+    let session_files = warp::get()
+        .and(warp::path!("sessions" / String / "files"))
+        .and(with_state(state.clone()))
+        .and(with_settings(settings.clone()))
+        .and_then(list_files);
+
+    let session_file_download = warp::get()
+        .and(warp::path!("sessions" / String / "files" / ..))
+        .and(with_file_name())
+        .and(warp::header::optional::<String>("range"))
+        .and(with_state(state.clone()))
+        .and(with_settings(settings.clone()))
+        .and_then(download_file);
+
+    let session_file_delete = warp::delete()
+        .and(warp::path!("sessions" / String / "files" / ..))
+        .and(with_file_name())
+        .and(with_state(state.clone()))
+        .and(with_settings(settings.clone()))
+        .and_then(delete_file);
+
     let routes = list
         .or(preview)
         .or(init)
+        .or(stream)
+        .or(websocket)
+        .or(events_stream)
         .or(grab)
         .or(start)
         .or(stop)
+        .or(composite)
+        .or(motion_start)
+        .or(motion_stop)
+        .or(motion)
+        .or(controls)
+        .or(presets)
+        .or(preset_save)
+        .or(preset_apply)
+        .or(files)
+        .or(file_download)
+        .or(file_delete)
+        .or(reconnects)
+        .or(skew)
+        .or(status)
         .or(dispose)
+        .or(session_create)
+        .or(sessions)
+        .or(session)
+        .or(session_delete)
+        .or(session_stream)
+        .or(session_websocket)
+        .or(session_events)
+        .or(session_grab)
+        .or(session_start)
+        .or(session_stop)
+        .or(session_files)
+        .or(session_file_download)
+        .or(session_file_delete)
         .recover(error::handle_rejection)
         .with(log);
 
@@ -70,3 +330,57 @@ fn with_state(
 ) -> impl Filter<Extract = (CaptureState,), Error = std::convert::Infallible> + Clone {
     warp::any().map(move || state.clone())
 }
+
+fn with_events(
+    events: EventState,
+) -> impl Filter<Extract = (EventState,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || events.clone())
+}
+
+fn with_settings(
+    settings: SettingsState,
+) -> impl Filter<Extract = (SettingsState,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || settings.clone())
+}
+
+/// Provides the default session ID to the routes without a session ID.
+fn with_default_session(
+) -> impl Filter<Extract = (String,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(|| DEFAULT_SESSION.to_string())
+}
+
+/// Provides the file naming of the request body, the configured naming if the body is empty.
+/// An invalid body is rejected.
+fn with_naming() -> impl Filter<Extract = (Naming,), Error = Rejection> + Clone {
+    let empty = warp::header::optional::<u64>("content-length").and_then(
+        |length: Option<u64>| async move {
+            match length.unwrap_or_default() {
+                0 => Ok(Naming::default()),
+                _ => Err(warp::reject()),
+            }
+        },
+    );
+    let parsed = warp::body::content_length_limit(NAMING_BODY_LIMIT)
+        .and(warp::body::bytes())
+        .and_then(|body: warp::hyper::body::Bytes| async move {
+            serde_json::from_slice::<Naming>(&body)
+                .map_err(|e| warp::reject::custom(error::ApiError::InvalidBody(e.to_string())))
+        });
+    empty.or(parsed).unify()
+}
+
+/// Provides a percent-decoded file path relative to the data directory
+/// from the rest of the request path.
+fn with_file_name() -> impl Filter<Extract = (String,), Error = Rejection> + Clone {
+    warp::path::tail().and_then(|tail: warp::path::Tail| async move {
+        percent_decode_str(tail.as_str())
+            .decode_utf8()
+            .map(|name| name.into_owned())
+            .map_err(|_| {
+                let name = tail.as_str().to_string();
+                warp::reject::custom(error::ApiError::Capture(CaptureError::InvalidFileName(
+                    name,
+                )))
+            })
+    })
+}
@@ -1,104 +1,920 @@
-use icapture_core::{device, Capture, Config};
-use std::sync::{Arc, Mutex};
+use futures_util::{stream, SinkExt, StreamExt};
+use icapture_core::{
+    composite::Composite,
+    config::ConfigError,
+    controls::Controls,
+    device,
+    events::CaptureEvent,
+    naming::Naming,
+    storage,
+    stream::{encode_jpeg, PreviewMode},
+    Capture, CaptureArtifact, CaptureError, CaptureStatus, Config, MultiCapture, Preset,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::convert::Infallible;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::MissedTickBehavior;
+use warp::http::header::{HeaderValue, ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE};
+use warp::http::StatusCode;
+use warp::hyper::Body;
+use warp::ws::{Message, WebSocket, Ws};
 use warp::{reject::Rejection, Reply};
 
 pub mod error;
 use error::*;
 
 type Result<T> = std::result::Result<T, Rejection>;
-pub(crate) type CaptureState = Arc<Mutex<Option<Capture>>>;
+pub(crate) type Session = Arc<Mutex<MultiCapture>>;
+pub(crate) type CaptureState = Arc<Mutex<BTreeMap<String, Session>>>;
+pub(crate) type EventState = broadcast::Sender<SessionEvent>;
+pub(crate) type SettingsState = Arc<Settings>;
+
+/// Session used by the routes without a session ID.
+pub(crate) const DEFAULT_SESSION: &str = "default";
+const STREAM_BOUNDARY: &str = "frame";
+const STREAM_CONTENT_TYPE: &str = "multipart/x-mixed-replace; boundary=frame";
+const FILE_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Server settings resolved from the server config file at startup.
+pub(crate) struct Settings {
+    /// Directory presets are stored in, next to the config file
+    pub(crate) preset_dir: PathBuf,
+    /// Data directory of the config file, served while the default session is not initialized
+    pub(crate) data_dir: PathBuf,
+}
+
+/// Capturing configuration of a single device or a list of devices recording in sync.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(crate) enum InitRequest {
+    Single(Config),
+    Multiple(Vec<Config>),
+}
+
+/// Capture session to create, identified by the device IDs if no ID is given.
+#[derive(Deserialize)]
+pub(crate) struct SessionRequest {
+    id: Option<String>,
+    config: InitRequest,
+}
+
+/// Device, frame rate and JPEG quality requested by a live stream viewer,
+/// the frame rate and quality are limited by the stream settings of the device.
+#[derive(Deserialize)]
+pub(crate) struct StreamQuery {
+    /// Index of the device in the session, the first device if not set
+    device: Option<usize>,
+    fps: Option<u32>,
+    quality: Option<u8>,
+}
+
+/// Preview mode requested by a client, the configured preview mode if not set.
+#[derive(Deserialize)]
+pub(crate) struct PreviewQuery {
+    mode: Option<PreviewMode>,
+}
+
+/// Capture event of a session.
+#[derive(Clone, Serialize)]
+pub(crate) struct SessionEvent {
+    session: String,
+    #[serde(flatten)]
+    event: CaptureEvent,
+}
+
+/// Files produced by capturing, always wrapped in a list.
+#[derive(Serialize)]
+pub(crate) struct ArtifactResponse {
+    artifacts: Vec<CaptureArtifact>,
+}
+
+/// Capture session with the status of its devices.
+#[derive(Serialize)]
+pub(crate) struct SessionInfo {
+    id: String,
+    devices: Vec<CaptureStatus>,
+}
+
+impl InitRequest {
+    fn into_configs(self) -> Vec<Config> {
+        match self {
+            InitRequest::Single(config) => vec![config],
+            InitRequest::Multiple(configs) => configs,
+        }
+    }
+}
+
+impl From<Vec<CaptureArtifact>> for ArtifactResponse {
+    fn from(artifacts: Vec<CaptureArtifact>) -> Self {
+        ArtifactResponse { artifacts }
+    }
+}
+
+impl SessionInfo {
+    fn new(id: &str, capture: &MultiCapture) -> Self {
+        SessionInfo {
+            id: id.to_string(),
+            devices: capture.captures().iter().map(Capture::status).collect(),
+        }
+    }
+}
+
+/// Gets the first device, which serves the endpoints that do not apply to all devices.
+fn primary(capture: &MultiCapture) -> &Capture {
+    &capture.captures()[0]
+}
+
+fn primary_mut(capture: &mut MultiCapture) -> &mut Capture {
+    &mut capture.captures_mut()[0]
+}
+
+/// Gets a device of a session by its index in the session configuration.
+fn device_at(capture: &MultiCapture, index: usize) -> Result<&Capture> {
+    capture
+        .captures()
+        .get(index)
+        .ok_or_else(|| warp::reject::custom(ApiError::DeviceNotInSession(index)))
+}
+
+fn not_found(id: &str) -> Rejection {
+    if id == DEFAULT_SESSION {
+        warp::reject::custom(ApiError::CaptureNotInitialized)
+    } else {
+        warp::reject::custom(ApiError::SessionNotFound(id.to_string()))
+    }
+}
+
+/// Maps a preset error, a missing preset file is reported as not found.
+fn preset_error(name: &str, err: ConfigError) -> Rejection {
+    match err {
+        ConfigError::Io(err) if err.kind() == io::ErrorKind::NotFound => {
+            warp::reject::custom(ApiError::PresetNotFound(name.to_string()))
+        }
+        err => warp::reject::custom(ApiError::Preset(err.to_string())),
+    }
+}
+
+/// Gets all sessions, the registry is only locked for the lookup.
+fn sessions(state: &CaptureState) -> Vec<(String, Session)> {
+    let state = state.lock().unwrap();
+    state
+        .iter()
+        .map(|(id, session)| (id.clone(), Arc::clone(session)))
+        .collect()
+}
+
+/// Runs blocking capture work on the blocking thread pool, so that the runtime stays responsive.
+async fn run_blocking<T, F>(work: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|e| warp::reject::custom(ApiError::Worker(e.to_string())))?
+}
+
+/// Runs a capture operation on a session, after the operations already running on it.
+async fn with_session<T, F>(state: &CaptureState, id: &str, operation: F) -> Result<T>
+where
+    F: FnOnce(&mut MultiCapture) -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    let session = state.lock().unwrap().get(id).cloned();
+    match session {
+        Some(session) => run_blocking(move || operation(&mut session.lock().unwrap())).await,
+        None => Err(not_found(id)),
+    }
+}
+
+/// Forwards capture events of a session to the subscribers of the server,
+/// until the devices of the session are released.
+fn forward_events(id: &str, events: &EventState) -> mpsc::Sender<CaptureEvent> {
+    let (sender, receiver) = mpsc::channel();
+    let session = id.to_string();
+    let events = events.clone();
+    thread::spawn(move || {
+        while let Ok(event) = receiver.recv() {
+            let _ = events.send(SessionEvent {
+                session: session.clone(),
+                event,
+            });
+        }
+    });
+    sender
+}
+
+/// Gets the latest frame of a session device as a JPEG image, `None` if no new frame is available.
+async fn live_jpeg(
+    state: &CaptureState,
+    id: &str,
+    device: usize,
+    quality: u8,
+) -> Result<Option<Vec<u8>>> {
+    with_session(state, id, move |capture| {
+        let frame = device_at(capture, device)?.live_frame().ok().flatten();
+        Ok(frame.and_then(|frame| encode_jpeg(&frame, quality).ok()))
+    })
+    .await
+}
+
+/// Wraps a JPEG image into a part of a multipart MJPEG stream.
+fn mjpeg_part(jpeg: &[u8]) -> Vec<u8> {
+    let mut part = format!(
+        "--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+        STREAM_BOUNDARY,
+        jpeg.len()
+    )
+    .into_bytes();
+    part.extend_from_slice(jpeg);
+    part.extend_from_slice(b"\r\n");
+    part
+}
+
+/// Parses a single byte range of a `Range` header into inclusive bounds,
+/// `None` if the range cannot be satisfied for a file of the given length.
+fn parse_range(range: &str, len: u64) -> Option<(u64, u64)> {
+    let (start, end) = range.trim().strip_prefix("bytes=")?.split_once('-')?;
+    match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().ok()?;
+            (suffix > 0 && len > 0).then(|| (len.saturating_sub(suffix), len - 1))
+        }
+        (start, "") => {
+            let start: u64 = start.parse().ok()?;
+            (start < len).then(|| (start, len - 1))
+        }
+        (start, end) => {
+            let start: u64 = start.parse().ok()?;
+            let end: u64 = end.parse().ok()?;
+            (start <= end && start < len).then(|| (start, end.min(len - 1)))
+        }
+    }
+}
+
+/// Gets a content type of a captured file from its extension.
+fn content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "mp4" => "video/mp4",
+        "avi" => "video/x-msvideo",
+        "mkv" => "video/x-matroska",
+        "webm" => "video/webm",
+        "wmv" => "video/x-ms-wmv",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Gets the data directory of a session, where its files are stored,
+/// the configured data directory if the default session is not initialized.
+async fn data_dir(state: &CaptureState, id: &str, settings: &Settings) -> Result<PathBuf> {
+    if id == DEFAULT_SESSION && !state.lock().unwrap().contains_key(id) {
+        return Ok(settings.data_dir.clone());
+    }
+    with_session(state, id, |capture| {
+        Ok(primary(capture).config.data_dir.clone())
+    })
+    .await
+}
+
+/// Opens the devices of a session, which must not be used by any other session.
+fn open_session(
+    sessions: &[(String, Session)],
+    id: &str,
+    mut configs: Vec<Config>,
+    events: mpsc::Sender<CaptureEvent>,
+    preset_dir: &Path,
+) -> Result<MultiCapture> {
+    let device_ids: HashSet<u32> = configs.iter().map(|config| config.device_id).collect();
+    for (other_id, other) in sessions.iter().filter(|(other_id, _)| other_id != id) {
+        let other = other.lock().unwrap();
+        if let Some(capture) = other
+            .captures()
+            .iter()
+            .find(|capture| device_ids.contains(&capture.config.device_id))
+        {
+            return Err(warp::reject::custom(ApiError::DeviceInUse(
+                capture.config.device_id,
+                other_id.clone(),
+            )));
+        }
+    }
+    for config in configs.iter_mut() {
+        config
+            .apply_preset(preset_dir)
+            .map_err(|e| preset_error(config.preset.as_deref().unwrap_or_default(), e))?;
+    }
+    let mut capture = MultiCapture::new_with_events(&configs, events)
+        .map_err(|e| warp::reject::custom(ApiError::Capture(e)))?;
+    capture.set_session(id);
+    Ok(capture)
+}
 
 pub(crate) async fn list_devices() -> Result<impl Reply> {
-    let list = device::enumerate_capture_devices().ok();
+    let list = run_blocking(|| Ok(device::enumerate_capture_devices().ok())).await?;
     match list {
         Some(list) => Ok(warp::reply::json(&list)),
-        None => Err(warp::reject::custom(ApiError::EnumerateDevices))
+        None => Err(warp::reject::custom(ApiError::EnumerateDevices)),
     }
 }
 
-pub(crate) async fn init_capture(config: Config, state: CaptureState) -> Result<impl Reply> {
-    let capture = Capture::new(&config)
-        .map_err(|e| warp::reject::custom(ApiError::Capture(e)))?;
+pub(crate) async fn init_capture(
+    request: InitRequest,
+    state: CaptureState,
+    events: EventState,
+    settings: SettingsState,
+) -> Result<impl Reply> {
+    let configs = request.into_configs();
+    let previous = state.lock().unwrap().remove(DEFAULT_SESSION);
+    let sessions = sessions(&state);
+    let session_configs = configs.clone();
+    let session_events = forward_events(DEFAULT_SESSION, &events);
+    let capture = run_blocking(move || {
+        // the previous default session is released first, as it may hold the same devices
+        if let Some(previous) = previous {
+            previous
+                .lock()
+                .unwrap()
+                .dispose()
+                .map_err(|e| warp::reject::custom(ApiError::Capture(e)))?;
+        }
+        open_session(
+            &sessions,
+            DEFAULT_SESSION,
+            session_configs,
+            session_events,
+            &settings.preset_dir,
+        )
+    })
+    .await?;
 
-    let mut state = state.lock().unwrap();
-    *state = Some(capture);
+    let replaced = state
+        .lock()
+        .unwrap()
+        .insert(DEFAULT_SESSION.to_string(), Arc::new(Mutex::new(capture)));
+    if let Some(replaced) = replaced {
+        // another init request completed while the devices were opening
+        run_blocking(move || {
+            replaced
+                .lock()
+                .unwrap()
+                .dispose()
+                .map_err(|e| warp::reject::custom(ApiError::Capture(e)))
+        })
+        .await?;
+    }
 
     Ok(warp::reply::json(&error::StatusResponse {
-        message: format!("capture initialized successfully: {:?}", &config),
+        message: format!("capture initialized successfully: {:?}", &configs),
     }))
 }
 
-pub(crate) async fn preview(state: CaptureState) -> Result<impl Reply> {
-    let mut state = state.lock().unwrap();
-    if let Some(capture) = state.as_mut() {
-        capture
-            .preview()
-            .map_err(|e| warp::reject::custom(ApiError::Capture(e)))?;
+pub(crate) async fn create_session(
+    request: SessionRequest,
+    state: CaptureState,
+    events: EventState,
+    settings: SettingsState,
+) -> Result<impl Reply> {
+    let configs = request.config.into_configs();
+    let id = request.id.unwrap_or_else(|| {
+        let device_ids: Vec<String> = configs.iter().map(|c| c.device_id.to_string()).collect();
+        format!("device-{}", device_ids.join("-"))
+    });
+    let valid_id = !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+    if !valid_id {
+        return Err(warp::reject::custom(ApiError::InvalidSessionId(id)));
+    }
+    if state.lock().unwrap().contains_key(&id) {
+        return Err(warp::reject::custom(ApiError::SessionExists(id)));
+    }
 
-        Ok(warp::reply::json(&StatusResponse {
-            message: "preview executed".to_string(),
-        }))
-    } else {
-        Err(warp::reject::custom(ApiError::CaptureNotInitialized))
+    let sessions = sessions(&state);
+    let session_id = id.clone();
+    let session_events = forward_events(&id, &events);
+    let mut capture = run_blocking(move || {
+        open_session(
+            &sessions,
+            &session_id,
+            configs,
+            session_events,
+            &settings.preset_dir,
+        )
+    })
+    .await?;
+    let session = SessionInfo::new(&id, &capture);
+    {
+        let mut state = state.lock().unwrap();
+        if !state.contains_key(&id) {
+            state.insert(id, Arc::new(Mutex::new(capture)));
+            return Ok(warp::reply::json(&session));
+        }
     }
+
+    // another request created the session while the devices were opening
+    run_blocking(move || {
+        capture
+            .dispose()
+            .map_err(|e| warp::reject::custom(ApiError::Capture(e)))
+    })
+    .await?;
+    Err(warp::reject::custom(ApiError::SessionExists(id)))
 }
 
-pub(crate) async fn grab_frame(state: CaptureState) -> Result<impl Reply> {
-    let mut state = state.lock().unwrap();
-    if let Some(capture) = state.as_mut() {
+pub(crate) async fn list_sessions(state: CaptureState) -> Result<impl Reply> {
+    let sessions = sessions(&state);
+    let sessions: Vec<SessionInfo> = run_blocking(move || {
+        Ok(sessions
+            .iter()
+            .map(|(id, session)| SessionInfo::new(id, &session.lock().unwrap()))
+            .collect())
+    })
+    .await?;
+    Ok(warp::reply::json(&sessions))
+}
+
+pub(crate) async fn get_session(id: String, state: CaptureState) -> Result<impl Reply> {
+    let session_id = id.clone();
+    let session = with_session(&state, &id, move |capture| {
+        Ok(SessionInfo::new(&session_id, capture))
+    })
+    .await?;
+    Ok(warp::reply::json(&session))
+}
+
+pub(crate) async fn preview(
+    id: String,
+    query: PreviewQuery,
+    state: CaptureState,
+) -> Result<impl Reply> {
+    let mode = with_session(&state, &id, move |capture| {
+        let capture = primary_mut(capture);
+        let mode = query.mode.unwrap_or(capture.config.stream.preview_mode);
         capture
-            .grab_frame()
-            .map_err(|e| warp::reject::custom(ApiError::Capture(e)))?;
+            .start_preview(mode)
+            .map(|_| mode)
+            .map_err(|e| warp::reject::custom(ApiError::Capture(e)))
+    })
+    .await?;
 
-        Ok(warp::reply::json(&StatusResponse {
-            message: "frame grabbed".to_string(),
-        }))
-    } else {
-        Err(warp::reject::custom(ApiError::CaptureNotInitialized))
+    Ok(warp::reply::json(&StatusResponse {
+        message: format!("{} preview started", mode.name()),
+    }))
+}
+
+pub(crate) async fn stream_mjpeg(
+    id: String,
+    query: StreamQuery,
+    state: CaptureState,
+) -> Result<impl Reply> {
+    let device = query.device.unwrap_or_default();
+    let settings = with_session(&state, &id, move |capture| {
+        Ok(device_at(capture, device)?.config.stream)
+    })
+    .await?;
+    let (fps, quality) = settings.limit(query.fps, query.quality);
+    let mut interval = tokio::time::interval(Duration::from_secs_f64(1.0 / fps as f64));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    // each viewer polls the latest frame, the stream ends when the session is disposed
+    let parts = stream::unfold(
+        (id, state, interval),
+        move |(id, state, mut interval)| async move {
+            loop {
+                interval.tick().await;
+                match live_jpeg(&state, &id, device, quality).await {
+                    Ok(Some(jpeg)) => {
+                        let part = Ok::<_, Infallible>(mjpeg_part(&jpeg));
+                        return Some((part, (id, state, interval)));
+                    }
+                    Ok(None) => continue,
+                    Err(_) => return None,
+                }
+            }
+        },
+    );
+
+    let mut response = warp::reply::Response::new(Body::wrap_stream(parts));
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static(STREAM_CONTENT_TYPE));
+    Ok(response)
+}
+
+pub(crate) async fn stream_websocket(
+    id: String,
+    ws: Ws,
+    query: StreamQuery,
+    state: CaptureState,
+    events: EventState,
+) -> Result<impl Reply> {
+    let device = query.device.unwrap_or_default();
+    let settings = with_session(&state, &id, move |capture| {
+        Ok(device_at(capture, device)?.config.stream)
+    })
+    .await?;
+    let (fps, quality) = settings.limit(query.fps, query.quality);
+    let events = events.subscribe();
+    Ok(ws.on_upgrade(move |socket| websocket(socket, id, device, fps, quality, state, events)))
+}
+
+/// Pushes frames of a session as binary messages and its events as JSON text messages,
+/// until the client disconnects or the session is disposed.
+async fn websocket(
+    socket: WebSocket,
+    id: String,
+    device: usize,
+    fps: u32,
+    quality: u8,
+    state: CaptureState,
+    mut events: broadcast::Receiver<SessionEvent>,
+) {
+    let (mut sender, mut receiver) = socket.split();
+    let mut interval = tokio::time::interval(Duration::from_secs_f64(1.0 / fps as f64));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    loop {
+        let message = tokio::select! {
+            _ = interval.tick() => match live_jpeg(&state, &id, device, quality).await {
+                Ok(Some(jpeg)) => Message::binary(jpeg),
+                Ok(None) => continue,
+                Err(_) => break,
+            },
+            event = events.recv() => match event {
+                Ok(event) if event.session == id => match serde_json::to_string(&event) {
+                    Ok(json) => Message::text(json),
+                    Err(_) => continue,
+                },
+                Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            },
+            incoming = receiver.next() => match incoming {
+                Some(Ok(message)) if !message.is_close() => continue,
+                _ => break,
+            },
+        };
+        if sender.send(message).await.is_err() {
+            break;
+        }
     }
+    let _ = sender.close().await;
+}
+
+pub(crate) async fn stream_events(
+    session: Option<String>,
+    events: EventState,
+) -> Result<impl Reply> {
+    let events = stream::unfold(events.subscribe(), move |mut events| {
+        let session = session.clone();
+        async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) if session.is_none() || session.as_ref() == Some(&event.session) => {
+                        let sse_event = warp::sse::Event::default()
+                            .event(event.event.kind.name())
+                            .json_data(&event);
+                        if let Ok(sse_event) = sse_event {
+                            return Some((Ok::<_, Infallible>(sse_event), events));
+                        }
+                    }
+                    Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        }
+    });
+    Ok(warp::sse::reply(warp::sse::keep_alive().stream(events)))
 }
 
-pub(crate) async fn start_grab_video(state: CaptureState) -> Result<impl Reply> {
-    let mut state = state.lock().unwrap();
-    if let Some(capture) = state.as_mut() {
+pub(crate) async fn grab_frame(
+    id: String,
+    naming: Naming,
+    state: CaptureState,
+) -> Result<impl Reply> {
+    let artifacts = with_session(&state, &id, move |capture| {
         capture
-            .start_grab_video()
-            .map_err(|e| warp::reject::custom(ApiError::Capture(e)))?;
+            .grab_frame_named(&naming)
+            .map_err(|e| warp::reject::custom(ApiError::Capture(e)))
+    })
+    .await?;
 
-        Ok(warp::reply::json(&StatusResponse {
-            message: "video grab started".to_string(),
-        }))
-    } else {
-        Err(warp::reject::custom(ApiError::CaptureNotInitialized))
-    }
+    Ok(warp::reply::json(&ArtifactResponse::from(artifacts)))
+}
+
+pub(crate) async fn start_grab_video(
+    id: String,
+    naming: Naming,
+    state: CaptureState,
+) -> Result<impl Reply> {
+    let outputs = with_session(&state, &id, move |capture| {
+        // a single device keeps the default file name, several devices share a session prefix
+        match capture.captures_mut() {
+            [single] => single
+                .start_grab_video_named(&naming)
+                .map(|output| vec![output]),
+            _ => capture
+                .start_grab_video_named(&naming)
+                .map(|session| session.outputs),
+        }
+        .map_err(|e| warp::reject::custom(ApiError::Capture(e)))
+    })
+    .await?;
+
+    Ok(warp::reply::json(&ArtifactResponse::from(outputs)))
 }
 
-pub(crate) async fn stop_grab_video(state: CaptureState) -> Result<impl Reply> {
-    let mut state = state.lock().unwrap();
-    if let Some(capture) = state.as_mut() {
+pub(crate) async fn start_grab_composite(
+    id: String,
+    composite: Composite,
+    state: CaptureState,
+) -> Result<impl Reply> {
+    let artifact = with_session(&state, &id, move |capture| {
+        capture
+            .start_grab_composite(&composite)
+            .map_err(|e| warp::reject::custom(ApiError::Capture(e)))
+    })
+    .await?;
+
+    Ok(warp::reply::json(&ArtifactResponse::from(vec![artifact])))
+}
+
+pub(crate) async fn stop_grab_video(id: String, state: CaptureState) -> Result<impl Reply> {
+    let artifacts = with_session(&state, &id, |capture| {
         capture
             .stop_grab_video()
+            .map_err(|e| warp::reject::custom(ApiError::Capture(e)))
+    })
+    .await?;
+
+    Ok(warp::reply::json(&ArtifactResponse::from(artifacts)))
+}
+
+pub(crate) async fn start_motion_monitor(id: String, state: CaptureState) -> Result<impl Reply> {
+    with_session(&state, &id, |capture| {
+        primary_mut(capture)
+            .start_motion_monitor()
+            .map_err(|e| warp::reject::custom(ApiError::Capture(e)))
+    })
+    .await?;
+
+    Ok(warp::reply::json(&StatusResponse {
+        message: "motion monitor started".to_string(),
+    }))
+}
+
+pub(crate) async fn stop_motion_monitor(id: String, state: CaptureState) -> Result<impl Reply> {
+    with_session(&state, &id, |capture| {
+        primary_mut(capture)
+            .stop_motion_monitor()
+            .map_err(|e| warp::reject::custom(ApiError::Capture(e)))
+    })
+    .await?;
+
+    Ok(warp::reply::json(&StatusResponse {
+        message: "motion monitor stopped".to_string(),
+    }))
+}
+
+pub(crate) async fn motion_events(id: String, state: CaptureState) -> Result<impl Reply> {
+    let events = with_session(&state, &id, |capture| Ok(primary(capture).motion_events())).await?;
+    Ok(warp::reply::json(&events))
+}
+
+pub(crate) async fn reconnect_events(id: String, state: CaptureState) -> Result<impl Reply> {
+    let events = with_session(&state, &id, |capture| {
+        Ok(primary(capture).reconnect_events())
+    })
+    .await?;
+    Ok(warp::reply::json(&events))
+}
+
+pub(crate) async fn set_controls(
+    id: String,
+    controls: Controls,
+    state: CaptureState,
+) -> Result<impl Reply> {
+    let controls = with_session(&state, &id, move |capture| {
+        primary_mut(capture)
+            .set_controls(&controls)
+            .map_err(|e| warp::reject::custom(ApiError::Capture(e)))
+    })
+    .await?;
+
+    Ok(warp::reply::json(&controls))
+}
+
+pub(crate) async fn list_presets(settings: SettingsState) -> Result<impl Reply> {
+    let names = Preset::list(&settings.preset_dir)
+        .map_err(|e| warp::reject::custom(ApiError::Preset(e.to_string())))?;
+    Ok(warp::reply::json(&names))
+}
+
+pub(crate) async fn save_preset(
+    id: String,
+    name: String,
+    state: CaptureState,
+    settings: SettingsState,
+) -> Result<impl Reply> {
+    let preset = with_session(&state, &id, move |capture| {
+        let preset = primary(capture)
+            .snapshot_preset(&name)
             .map_err(|e| warp::reject::custom(ApiError::Capture(e)))?;
+        preset
+            .save(&settings.preset_dir)
+            .map_err(|e| warp::reject::custom(ApiError::Preset(e.to_string())))?;
+        Ok(preset)
+    })
+    .await?;
 
-        Ok(warp::reply::json(&StatusResponse {
-            message: "video grab stopped".to_string(),
-        }))
-    } else {
-        Err(warp::reject::custom(ApiError::CaptureNotInitialized))
+    Ok(warp::reply::json(&preset))
+}
+
+pub(crate) async fn apply_preset(
+    id: String,
+    name: String,
+    state: CaptureState,
+    settings: SettingsState,
+) -> Result<impl Reply> {
+    let preset = Preset::load(&settings.preset_dir, &name).map_err(|e| preset_error(&name, e))?;
+    let controls = with_session(&state, &id, move |capture| {
+        primary_mut(capture)
+            .apply_preset(&preset)
+            .map_err(|e| warp::reject::custom(ApiError::Capture(e)))
+    })
+    .await?;
+
+    Ok(warp::reply::json(&controls))
+}
+
+pub(crate) async fn list_files(
+    id: String,
+    state: CaptureState,
+    settings: SettingsState,
+) -> Result<impl Reply> {
+    let data_dir = data_dir(&state, &id, &settings).await?;
+    let files = run_blocking(move || {
+        storage::list_files(&data_dir).map_err(|e| warp::reject::custom(ApiError::Capture(e)))
+    })
+    .await?;
+    Ok(warp::reply::json(&files))
+}
+
+pub(crate) async fn download_file(
+    id: String,
+    name: String,
+    range: Option<String>,
+    state: CaptureState,
+    settings: SettingsState,
+) -> Result<impl Reply> {
+    let data_dir = data_dir(&state, &id, &settings).await?;
+    let path = storage::resolve_file(&data_dir, &name)
+        .map_err(|e| warp::reject::custom(ApiError::Capture(e)))?;
+    let access_error =
+        || warp::reject::custom(ApiError::Capture(CaptureError::FileAccess(name.clone())));
+    let mut file = tokio::fs::File::open(&path)
+        .await
+        .map_err(|_| access_error())?;
+    let len = file.metadata().await.map_err(|_| access_error())?.len();
+
+    // multiple ranges are not supported, the whole file is sent instead
+    let range = range.filter(|range| !range.contains(','));
+    let (status, start, end) = match range {
+        Some(range) => match parse_range(&range, len) {
+            Some((start, end)) => (StatusCode::PARTIAL_CONTENT, start, end),
+            None => {
+                let mut response = warp::reply::Response::new(Body::empty());
+                *response.status_mut() = StatusCode::RANGE_NOT_SATISFIABLE;
+                response.headers_mut().insert(
+                    CONTENT_RANGE,
+                    HeaderValue::from_str(&format!("bytes */{}", len)).unwrap(),
+                );
+                return Ok(response);
+            }
+        },
+        None => (StatusCode::OK, 0, len.saturating_sub(1)),
+    };
+    let length = if len == 0 { 0 } else { end - start + 1 };
+    file.seek(std::io::SeekFrom::Start(start))
+        .await
+        .map_err(|_| access_error())?;
+
+    let chunks = stream::unfold(
+        (file.take(length), vec![0; FILE_CHUNK_SIZE]),
+        |(mut file, mut buffer)| async move {
+            match file.read(&mut buffer).await {
+                Ok(0) => None,
+                Ok(count) => {
+                    let chunk = Ok::<_, std::io::Error>(buffer[..count].to_vec());
+                    Some((chunk, (file, buffer)))
+                }
+                Err(e) => Some((Err(e), (file, buffer))),
+            }
+        },
+    );
+
+    let mut response = warp::reply::Response::new(Body::wrap_stream(chunks));
+    *response.status_mut() = status;
+    let headers = response.headers_mut();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type(&path)));
+    headers.insert(CONTENT_LENGTH, HeaderValue::from(length));
+    headers.insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    if status == StatusCode::PARTIAL_CONTENT {
+        let content_range = format!("bytes {}-{}/{}", start, end, len);
+        headers.insert(
+            CONTENT_RANGE,
+            HeaderValue::from_str(&content_range).unwrap(),
+        );
     }
+    Ok(response)
 }
 
-pub(crate) async fn dispose_capture(state: CaptureState) -> Result<impl Reply> {
-    let mut state = state.lock().unwrap();
-    if let Some(mut capture) = state.take() {
-        capture
-            .dispose()
-            .map_err(|e| warp::reject::custom(ApiError::Capture(e)))?;
+pub(crate) async fn delete_file(
+    id: String,
+    name: String,
+    state: CaptureState,
+    settings: SettingsState,
+) -> Result<impl Reply> {
+    let data_dir = data_dir(&state, &id, &settings).await?;
+    run_blocking(move || {
+        storage::delete_file(&data_dir, &name)
+            .map_err(|e| warp::reject::custom(ApiError::Capture(e)))
+    })
+    .await?;
 
-        Ok(warp::reply::json(&StatusResponse {
-            message: "capture disposed".to_string(),
-        }))
-    } else {
-        Err(warp::reject::custom(ApiError::CaptureNotInitialized))
+    Ok(warp::reply::json(&StatusResponse {
+        message: "file deleted".to_string(),
+    }))
+}
+
+pub(crate) async fn status(id: String, state: CaptureState) -> Result<impl Reply> {
+    let status = with_session(&state, &id, |capture| Ok(primary(capture).status())).await?;
+    Ok(warp::reply::json(&status))
+}
+
+pub(crate) async fn frame_skew(id: String, state: CaptureState) -> Result<impl Reply> {
+    let skew = with_session(&state, &id, |capture| Ok(capture.frame_skew())).await?;
+    Ok(warp::reply::json(&skew))
+}
+
+pub(crate) async fn dispose_capture(id: String, state: CaptureState) -> Result<impl Reply> {
+    let session = state.lock().unwrap().remove(&id);
+    match session {
+        Some(session) => {
+            run_blocking(move || {
+                session
+                    .lock()
+                    .unwrap()
+                    .dispose()
+                    .map_err(|e| warp::reject::custom(ApiError::Capture(e)))
+            })
+            .await?;
+
+            Ok(warp::reply::json(&StatusResponse {
+                message: "capture disposed".to_string(),
+            }))
+        }
+        None => Err(not_found(&id)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Some((0, 99)));
+        assert_eq!(parse_range("bytes=900-", 1000), Some((900, 999)));
+        // suffix ranges count from the end and are limited to the file length
+        assert_eq!(parse_range("bytes=-100", 1000), Some((900, 999)));
+        assert_eq!(parse_range("bytes=-2000", 1000), Some((0, 999)));
+        assert_eq!(parse_range("bytes=-0", 1000), None);
+        // the end is limited to the last byte of the file
+        assert_eq!(parse_range("bytes=500-1000", 1000), Some((500, 999)));
+        assert_eq!(parse_range("bytes=500-5000", 1000), Some((500, 999)));
+        assert_eq!(parse_range("bytes=1000-", 1000), None);
+        assert_eq!(parse_range("bytes=1000-2000", 1000), None);
+        assert_eq!(parse_range("bytes=100-50", 1000), None);
+        // an empty file has no satisfiable range
+        assert_eq!(parse_range("bytes=0-", 0), None);
+        assert_eq!(parse_range("bytes=0-0", 0), None);
+        assert_eq!(parse_range("bytes=-1", 0), None);
+        assert_eq!(parse_range("items=0-99", 1000), None);
+        assert_eq!(parse_range("bytes=a-b", 1000), None);
     }
 }
@@ -17,6 +17,24 @@ pub(crate) enum ApiError {
     CaptureNotInitialized,
     #[error("cannot enumerate capture devices")]
     EnumerateDevices,
+    #[error("preset error: {0}")]
+    Preset(String),
+    #[error("preset '{0}' not found")]
+    PresetNotFound(String),
+    #[error("session '{0}' not found")]
+    SessionNotFound(String),
+    #[error("session '{0}' already exists")]
+    SessionExists(String),
+    #[error("invalid session id '{0}'")]
+    InvalidSessionId(String),
+    #[error("device {0} is used by session '{1}'")]
+    DeviceInUse(u32, String),
+    #[error("session has no device at index {0}")]
+    DeviceNotInSession(usize),
+    #[error("capture worker failed: {0}")]
+    Worker(String),
+    #[error("invalid request body: {0}")]
+    InvalidBody(String),
 }
 impl warp::reject::Reject for ApiError {}
 
@@ -29,6 +47,14 @@ pub(crate) async fn handle_rejection(
                 warp::http::StatusCode::INTERNAL_SERVER_ERROR,
                 format!("cannot create file or directory '{}'", path),
             ),
+            ApiError::Capture(CaptureError::FileAccess(name)) => (
+                warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+                format!("cannot access file '{}'", name),
+            ),
+            ApiError::Capture(CaptureError::FileNotFound(name)) => (
+                warp::http::StatusCode::NOT_FOUND,
+                format!("file '{}' not found", name),
+            ),
             ApiError::Capture(CaptureError::DeviceOpen(device)) => (
                 warp::http::StatusCode::INTERNAL_SERVER_ERROR,
                 format!("cannot open capture device {}", device),
@@ -37,6 +63,18 @@ pub(crate) async fn handle_rejection(
                 warp::http::StatusCode::INTERNAL_SERVER_ERROR,
                 "cannot grab a frame".to_string(),
             ),
+            ApiError::Capture(CaptureError::FrameQuality(issues)) => (
+                warp::http::StatusCode::UNPROCESSABLE_ENTITY,
+                format!("frame quality check failed: {:?}", issues),
+            ),
+            ApiError::Capture(CaptureError::InvalidFileName(name)) => (
+                warp::http::StatusCode::BAD_REQUEST,
+                format!("invalid file name '{}'", name),
+            ),
+            ApiError::Capture(CaptureError::InvalidConfig(reason)) => (
+                warp::http::StatusCode::BAD_REQUEST,
+                format!("invalid configuration: {}", reason),
+            ),
             ApiError::Capture(CaptureError::OpenCv(error)) => (
                 warp::http::StatusCode::INTERNAL_SERVER_ERROR,
                 format!("opencv error: {}", error),
@@ -45,6 +83,10 @@ pub(crate) async fn handle_rejection(
                 warp::http::StatusCode::INTERNAL_SERVER_ERROR,
                 "resource is busy".to_string(),
             ),
+            ApiError::Capture(CaptureError::VideoWriterOpen(path)) => (
+                warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+                format!("cannot open video writer for '{}'", path),
+            ),
             ApiError::CaptureNotInitialized => (
                 warp::http::StatusCode::BAD_REQUEST,
                 "capture not initialized".to_string(),
@@ -53,6 +95,42 @@ pub(crate) async fn handle_rejection(
                 warp::http::StatusCode::INTERNAL_SERVER_ERROR,
                 "cannot enumerate capture devices".to_string(),
             ),
+            ApiError::Preset(reason) => (
+                warp::http::StatusCode::BAD_REQUEST,
+                format!("preset error: {}", reason),
+            ),
+            ApiError::PresetNotFound(name) => (
+                warp::http::StatusCode::NOT_FOUND,
+                format!("preset '{}' not found", name),
+            ),
+            ApiError::SessionNotFound(id) => (
+                warp::http::StatusCode::NOT_FOUND,
+                format!("session '{}' not found", id),
+            ),
+            ApiError::SessionExists(id) => (
+                warp::http::StatusCode::CONFLICT,
+                format!("session '{}' already exists", id),
+            ),
+            ApiError::InvalidSessionId(id) => (
+                warp::http::StatusCode::BAD_REQUEST,
+                format!("invalid session id '{}'", id),
+            ),
+            ApiError::DeviceInUse(device, id) => (
+                warp::http::StatusCode::CONFLICT,
+                format!("device {} is used by session '{}'", device, id),
+            ),
+            ApiError::DeviceNotInSession(index) => (
+                warp::http::StatusCode::NOT_FOUND,
+                format!("session has no device at index {}", index),
+            ),
+            ApiError::Worker(reason) => (
+                warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+                format!("capture worker failed: {}", reason),
+            ),
+            ApiError::InvalidBody(reason) => (
+                warp::http::StatusCode::BAD_REQUEST,
+                format!("invalid request body: {}", reason),
+            ),
         }
     } else {
         (